@@ -1,7 +1,11 @@
-function _update()
+function _update(world, self)
     
 end
 
-function _onload()
+function _onload(world, self)
     
-end
\ No newline at end of file
+end
+
+function _on_use(world, self, side)
+    
+end
@@ -3,7 +3,7 @@ use std::{path::PathBuf, str::FromStr};
 use json::JsonValue;
 use rand::Rng;
 
-use crate::{ai::Animator, audio::Song, effect::Effect, entity::{Entity, VariableValue}, game::{BoolProperty, Condition, Direction, EntityPropertyType, FloatProperty, IntProperty, LevelPropertyType, PlayerPropertyType, PropertyLocation, QueuedLoad, StringProperty, WarpPos}, player::Player, transitions::Transition, world::{QueuedEntityAction, World}};
+use crate::{ai::Animator, audio::Song, effect::Effect, entity::{Entity, VariableValue}, game::{BoolProperty, Condition, Direction, EntityPropertyType, FloatProperty, IntProperty, LevelPropertyType, PlayerPropertyType, PropertyLocation, QueuedLoad, StringProperty, WarpPos}, lua::{ScriptArg, ScriptCall}, player::Player, transitions::Transition, world::{QueuedEntityAction, World}};
 
 pub fn parse_action(parsed: &JsonValue) -> Result<Box<dyn Action>, String> {
     if parsed.is_array() {
@@ -35,6 +35,7 @@ pub fn parse_action(parsed: &JsonValue) -> Result<Box<dyn Action>, String> {
         "random" => { return RandomAction::parse(parsed); },
         "set_layer_visible" => { return SetLayerVisibleAction::parse(parsed) },
         "unfreeze" => { return UnfreezeAction::parse(parsed) },
+        "lua" => { return LuaAction::parse(parsed) },
         _ => {
             return Err(format!("Unknown action \"{}\"", parsed["type"].as_str().unwrap()));
         }
@@ -965,7 +966,7 @@ impl RandomAction {
     pub fn poll_rand(&self, player: &Player, world: &World) -> f32 {
         match self.source {
             RandomSource::Level => world.random.level_random,
-            RandomSource::Pure => rand::thread_rng().gen_range(0.0..1.0),
+            RandomSource::Pure => world.random.rng.gen_range(0.0..1.0),
             RandomSource::Save => player.random,
             RandomSource::Session => world.random.session_random
         }
@@ -1068,4 +1069,77 @@ impl Action for UnfreezeAction {
         player.frozen_time = 0;
         player.frozen = false;
     }
-}
\ No newline at end of file
+}
+
+enum LuaActionArg {
+    Int(IntProperty),
+    String(StringProperty)
+}
+
+/// Call a lua function in the entity's script, the map's script, or a library script in `res/scripts/`
+pub struct LuaAction {
+    pub function: String,
+    pub library: Option<PathBuf>,
+    args: Vec<LuaActionArg>
+}
+
+impl LuaAction {
+    pub fn parse(parsed: &JsonValue) -> Result<Box<dyn Action>, String> {
+        if !parsed["function"].is_string() {
+            return Err("No function specified for lua action".to_string());
+        }
+
+        let library = parsed["library"].as_str().map(|library| PathBuf::from("res/scripts/").join(library));
+
+        let mut args = Vec::new();
+        for arg in parsed["args"].members() {
+            if arg.is_string() {
+                args.push(LuaActionArg::String(StringProperty::String(arg.as_str().unwrap().to_string())));
+            } else if let Some(int) = IntProperty::parse(arg) {
+                args.push(LuaActionArg::Int(int));
+            } else {
+                args.push(LuaActionArg::String(StringProperty::parse(arg).map_err(|e| format!("Invalid lua action argument: {}", e))?));
+            }
+        }
+
+        Ok(Box::new(Self {
+            function: parsed["function"].as_str().unwrap().to_string(),
+            library,
+            args
+        }))
+    }
+}
+
+impl Action for LuaAction {
+    fn act(&self, player: &mut Player, world: &mut World) {
+        let mut args = Vec::new();
+        for arg in self.args.iter() {
+            match arg {
+                LuaActionArg::Int(int) => {
+                    if let Some(i) = int.get(Some(player), Some(world)) {
+                        args.push(ScriptArg::Int(i));
+                    } else {
+                        eprintln!("Warning: lua action argument for \"{}\" could not be evaluated", self.function);
+                        return;
+                    }
+                },
+                LuaActionArg::String(string) => {
+                    if let Some(s) = string.get(Some(player), Some(world)) {
+                        args.push(ScriptArg::String(s));
+                    } else {
+                        eprintln!("Warning: lua action argument for \"{}\" could not be evaluated", self.function);
+                        return;
+                    }
+                }
+            }
+        }
+
+        let entity = if world.special_context.entity_context.entity_call { Some(world.special_context.entity_id) } else { None };
+        world.special_context.script_calls.push(ScriptCall {
+            entity,
+            library: self.library.clone(),
+            function: self.function.clone(),
+            args
+        });
+    }
+}
@@ -249,8 +249,9 @@ impl Ai for Wander {
         //dbg!(self.timer);
         if self.timer == 0 {
             
-            if (rand::random::<f32>() * self.frequency as f32).round() as i32 == 0 {
-                entity.walk(rand::random::<Direction>(), world, player, entity_list);
+            if (world.random.rng.gen::<f32>() * self.frequency as f32).round() as i32 == 0 {
+                let direction = world.random.rng.gen::<Direction>();
+                entity.walk(direction, world, player, entity_list);
                 self.timer = self.delay;
             }
         }
@@ -263,6 +264,26 @@ impl Ai for MoveStraight {
     }
 }
 
+/// Hands each tick to `function(ai, self)` in the entity's lua script
+pub struct ScriptAi {
+    pub function: String,
+    pub speed: u32,
+    pub move_delay: u32
+}
+
+impl Ai for ScriptAi {
+    fn act(&mut self, entity: &mut Entity, world: &mut World, _player: &Player, _entity_list: &Vec<Entity>) {
+        if entity.movement.is_none() {
+            entity.init_movement();
+            entity.movement.as_mut().unwrap().speed = self.speed;
+            entity.movement.as_mut().unwrap().delay = self.move_delay;
+        }
+
+        // The script runs once the entity list is back in the world
+        world.special_context.script_ai_ticks.push((entity.id, self.function.clone()));
+    }
+}
+
 pub enum PathfinderType {
     AStar,
     WalkTowards,
@@ -423,12 +444,12 @@ impl Ai for Bird {
             self.init = true;
             entity.init_movement();
             entity.movement.as_mut().unwrap().speed = self.speed;
-            self.cur_direction = if rand::thread_rng().gen::<bool>() {Direction::Left} else {Direction::Right};
+            self.cur_direction = if world.random.rng.gen::<bool>() {Direction::Left} else {Direction::Right};
         }
 
         if !entity.movement.as_ref().unwrap().moving {
-            if rand::thread_rng().gen_range(0.0..1.0) < 0.025 {
-                if rand::thread_rng().gen::<bool>() {
+            if world.random.rng.gen_range(0.0..1.0) < 0.025 {
+                if world.random.rng.gen::<bool>() {
                     entity.walk(Direction::Up, world, player, entity_list);
                 } else {
                     entity.walk(Direction::Down, world, player, entity_list);
@@ -575,6 +596,16 @@ pub fn parse_ai(parsed: &JsonValue) -> Result<Box::<dyn Ai>, &str> {
                 speed
             }));
         }
+        "script" => {
+            let function = parsed["function"].as_str().ok_or("No function specified for script ai")?.to_string();
+            let speed = parsed["speed"].as_u32().unwrap_or(2);
+            let move_delay = parsed["move_delay"].as_u32().unwrap_or(0);
+            return Ok(Box::new(ScriptAi {
+                function,
+                speed,
+                move_delay
+            }));
+        },
         _ => return Err("Unknown ai type")
     }
 }
@@ -1101,16 +1132,16 @@ impl PolledPathfinder for ErraticPathfinder {
             suggested_direction = direction;
         }
 
-        if rand::thread_rng().gen_range(0.0..1.0) < 0.1 {
-            suggested_direction = rand::thread_rng().gen::<Direction>();
+        if world.random.rng.gen_range(0.0..1.0) < 0.1 {
+            suggested_direction = world.random.rng.gen::<Direction>();
         }
 
         return Some(suggested_direction);
     }
 
-    fn idle(&mut self, _: u32, _: u32, _: i32, _: &Player, _: &mut World, _: &Vec<Entity>) -> Option<Direction> {
-        if rand::thread_rng().gen_range(0.0..1.0) < 0.005 {
-            return Some(rand::thread_rng().gen::<Direction>());
+    fn idle(&mut self, _: u32, _: u32, _: i32, _: &Player, world: &mut World, _: &Vec<Entity>) -> Option<Direction> {
+        if world.random.rng.gen_range(0.0..1.0) < 0.005 {
+            return Some(world.random.rng.gen::<Direction>());
         }
 
         None
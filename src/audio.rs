@@ -4,10 +4,11 @@ use rodio::{Sink, Decoder, Source, source::{Repeat, Buffered}, OutputStreamHandl
 
 pub struct SoundEffectBank {
     pub sound_effects: HashMap<String, SoundEffect>,
-    pub output_handle: Arc<OutputStreamHandle>
+    /// `None` when there is no audio device, sound effects are skipped
+    pub output_handle: Option<Arc<OutputStreamHandle>>
 }
 
-const ACCEPTED_SFX_EXTENSIONS: [&str; 3] = [
+pub const ACCEPTED_SFX_EXTENSIONS: [&str; 3] = [
     "mp3", "wav", "ogg"
 ];
 
@@ -15,7 +16,15 @@ impl SoundEffectBank {
     pub fn new(output_handle: Arc<OutputStreamHandle>) -> Self {
         Self {
             sound_effects: HashMap::new(),
-            output_handle
+            output_handle: Some(output_handle)
+        }
+    }
+
+    /// A bank that plays nothing, for running without an audio device
+    pub fn silent() -> Self {
+        Self {
+            sound_effects: HashMap::new(),
+            output_handle: None
         }
     }
 
@@ -43,8 +52,10 @@ impl SoundEffectBank {
     }
 
     pub fn play(&mut self, name: &str) {
+        if self.output_handle.is_none() { return; }
+
         if self.sound_effects.contains_key(name) {
-            self.sound_effects.get(name).unwrap().play(&self.output_handle);
+            self.sound_effects.get(name).unwrap().play(self.output_handle.as_ref().unwrap());
         } else {
             if self.try_load(name, 1.0, 1.0) {
                 self.play(name);
@@ -55,8 +66,10 @@ impl SoundEffectBank {
     }
 
     pub fn play_ex(&mut self, name: &str, speed: f32, volume: f32) {
+        if self.output_handle.is_none() { return; }
+
         if self.sound_effects.contains_key(name) {
-            self.sound_effects.get(name).unwrap().play_ex(&self.output_handle, speed, volume);
+            self.sound_effects.get(name).unwrap().play_ex(self.output_handle.as_ref().unwrap(), speed, volume);
         } else {
             if self.try_load(name, speed, volume) {
                 self.play_ex(name, speed, volume);
@@ -0,0 +1,279 @@
+use std::{collections::HashMap, fs, panic, path::{Path, PathBuf}};
+
+use json::JsonValue;
+use tiled::{LayerType, Loader, Map, PropertyValue};
+
+use crate::{actions, ai, audio::ACCEPTED_SFX_EXTENSIONS, entity, loader, particles, world::PARTICLE_IMAGES_PATH};
+
+const EVENTS_PATH: &str = "res/data/event/";
+const SCRIPTS_PATH: &str = "res/scripts/";
+const TEXTURES_PATH: &str = "res/textures/";
+const MUSIC_PATH: &str = "res/audio/music/";
+
+/// Runs a parser that may panic on bad input, turning the panic into an error
+fn guarded<T, F: FnOnce() -> Result<T, String>>(parse: F) -> Result<T, String> {
+    match panic::catch_unwind(panic::AssertUnwindSafe(parse)) {
+        Ok(result) => result,
+        Err(payload) => {
+            if let Some(message) = payload.downcast_ref::<&str>() {
+                Err(message.to_string())
+            } else if let Some(message) = payload.downcast_ref::<String>() {
+                Err(message.clone())
+            } else {
+                Err("parser panicked".to_string())
+            }
+        }
+    }
+}
+
+fn missing(kind: &str, path: &Path) -> String {
+    format!("{} {} not found", kind, path.display())
+}
+
+fn sound_exists(name: &str) -> bool {
+    ACCEPTED_SFX_EXTENSIONS.iter().any(|extension| PathBuf::from(format!("res/audio/sfx/{}.{}", name, extension)).exists())
+}
+
+/// Checks that a screen event file and the texture and songs it uses exist
+fn check_event(name: &str, failures: &mut Vec<String>) {
+    let path = PathBuf::from(EVENTS_PATH).join(format!("{}.svt", name));
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(_) => {
+            failures.push(missing("screen event", &path));
+            return;
+        }
+    };
+
+    for line in source.split(&['\n', ';']) {
+        let tokens = line.split_whitespace().collect::<Vec<&str>>();
+        if tokens.first().is_some_and(|token| token.starts_with('#')) && tokens.get(1) == Some(&"texture") {
+            if let Some(texture) = tokens.get(2) {
+                let texture = PathBuf::from(TEXTURES_PATH).join(texture);
+                if !texture.exists() {
+                    failures.push(format!("screen event {}: {}", name, missing("texture", &texture)));
+                }
+            }
+        }
+        if let Some(i) = tokens.iter().position(|token| *token == "song") {
+            if let Some(song) = tokens.get(i + 1) {
+                let song = PathBuf::from(MUSIC_PATH).join(format!("{}.ogg", song));
+                if !song.exists() {
+                    failures.push(format!("screen event {}: {}", name, missing("song", &song)));
+                }
+            }
+        }
+    }
+}
+
+/// Walks an action and everything nested in it, checking the files it refers to
+fn check_action_files(action: &JsonValue, failures: &mut Vec<String>) {
+    if action.is_object() {
+        match action["type"].as_str() {
+            Some("play") => {
+                if let Some(sound) = action["sound"].as_str() {
+                    if !sound_exists(sound) {
+                        failures.push(format!("sound {} not found in res/audio/sfx/", sound));
+                    }
+                }
+            },
+            Some("change_song") => {
+                if let Some(song) = action["song"].as_str() {
+                    if !Path::new(song).exists() {
+                        failures.push(missing("song", Path::new(song)));
+                    }
+                }
+            },
+            Some("play_event") => {
+                if let Some(event) = action["event"].as_str() {
+                    check_event(event, failures);
+                }
+            },
+            Some("lua") => {
+                if let Some(library) = action["library"].as_str() {
+                    let library = PathBuf::from(SCRIPTS_PATH).join(library);
+                    if !library.exists() {
+                        failures.push(missing("script", &library));
+                    }
+                }
+            },
+            _ => ()
+        }
+
+        for (_, field) in action.entries() {
+            check_action_files(field, failures);
+        }
+    } else if action.is_array() {
+        for member in action.members() {
+            check_action_files(member, failures);
+        }
+    }
+}
+
+fn parse_property(properties: &HashMap<String, PropertyValue>, name: &str) -> Option<Result<JsonValue, String>> {
+    match properties.get(name) {
+        Some(PropertyValue::StringValue(source)) => Some(json::parse(source).map_err(|e| format!("{}: invalid json: {}", name, e))),
+        _ => None
+    }
+}
+
+/// Parses every definition on one object the same way the map loader would
+fn check_object(properties: &HashMap<String, PropertyValue>, failures: &mut Vec<String>) {
+    if let Some(parsed) = parse_property(properties, "actions") {
+        match parsed {
+            Ok(parsed) => {
+                for (i, entry) in parsed.members().enumerate() {
+                    let mut trigger = entry["trigger"].clone();
+                    if let Err(e) = guarded(|| entity::parse_trigger(&mut trigger).ok_or("invalid trigger".to_string())) {
+                        failures.push(format!("actions[{}].trigger: {}", i, e));
+                    }
+                    if let Err(e) = guarded(|| actions::parse_action(&entry["action"])) {
+                        failures.push(format!("actions[{}].action: {}", i, e));
+                    }
+                    check_action_files(&entry["action"], failures);
+                }
+            },
+            Err(e) => failures.push(e)
+        }
+    }
+
+    if let Some(parsed) = parse_property(properties, "ai") {
+        if let Err(e) = parsed.and_then(|parsed| guarded(|| ai::parse_ai(&parsed).map(|_| ()).map_err(|e| e.to_string()))) {
+            failures.push(format!("ai: {}", e));
+        }
+    }
+
+    if let Some(parsed) = parse_property(properties, "animation") {
+        if let Err(e) = parsed.and_then(|parsed| guarded(|| ai::parse_animator(&parsed, 0, 1).map(|_| ()).map_err(|e| e.to_string()))) {
+            failures.push(format!("animation: {}", e));
+        }
+    }
+
+    if let Some(parsed) = parse_property(properties, "collider") {
+        if let Err(e) = parsed.and_then(|parsed| guarded(|| Ok(loader::parse_rect(&parsed)))) {
+            failures.push(format!("collider: {}", e));
+        }
+    }
+
+    if let Some(parsed) = parse_property(properties, "particles") {
+        match parsed.and_then(|parsed| guarded(|| particles::parse_particles(&parsed).ok_or("invalid particles".to_string()))) {
+            Ok(emitter) => {
+                let texture = PathBuf::from(PARTICLE_IMAGES_PATH).join(&emitter.texture);
+                if !texture.exists() {
+                    failures.push(format!("particles: {}", missing("texture", &texture)));
+                }
+            },
+            Err(e) => failures.push(format!("particles: {}", e))
+        }
+    }
+
+    if let Some(PropertyValue::StringValue(script)) = properties.get("script") {
+        let script = PathBuf::from(SCRIPTS_PATH).join(script);
+        if !script.exists() {
+            failures.push(missing("script", &script));
+        }
+    }
+}
+
+fn check_map_properties(map: &Map, failures: &mut Vec<String>) {
+    if let Some(PropertyValue::StringValue(song)) = map.properties.get("music") {
+        if !Path::new(song).exists() {
+            failures.push(format!("music: {}", missing("song", Path::new(song))));
+        }
+    }
+
+    if let Some(PropertyValue::StringValue(script)) = map.properties.get("script") {
+        let script = PathBuf::from(SCRIPTS_PATH).join(script);
+        if !script.exists() {
+            failures.push(format!("script: {}", missing("script", &script)));
+        }
+    }
+
+    if let Some(PropertyValue::StringValue(edges)) = map.properties.get("edges") {
+        match json::parse(edges) {
+            Ok(parsed) => {
+                for (side, action) in parsed.entries() {
+                    if let Err(e) = guarded(|| actions::parse_action(action)) {
+                        failures.push(format!("edges.{}: {}", side, e));
+                    }
+                    check_action_files(action, failures);
+                }
+            },
+            Err(e) => failures.push(format!("edges: invalid json: {}", e))
+        }
+    }
+
+    for tileset in map.tilesets().iter() {
+        match &tileset.image {
+            Some(image) if !image.source.exists() => failures.push(format!("tileset {}: {}", tileset.name, missing("texture", &image.source))),
+            Some(_) => (),
+            None => failures.push(format!("tileset {}: tileset has no source image", tileset.name))
+        }
+    }
+}
+
+/// Loads a map and every definition it uses, returning what failed
+pub fn check_map(path: &Path) -> Vec<String> {
+    let map = match Loader::new().load_tmx_map(path) {
+        Ok(map) => map,
+        Err(e) => return vec![format!("could not load map: {}", e)]
+    };
+    let mut failures = Vec::new();
+    check_map_properties(&map, &mut failures);
+
+    for layer in map.layers() {
+        match layer.layer_type() {
+            LayerType::Objects(object_layer) => {
+                for object in object_layer.objects() {
+                    let mut object_failures = Vec::new();
+                    match loader::merged_properties(&object.properties) {
+                        Ok(properties) => check_object(&properties, &mut object_failures),
+                        Err(e) => object_failures.push(format!("file: {}", e))
+                    }
+                    failures.extend(object_failures.into_iter().map(|failure| format!("object {}: {}", object.id(), failure)));
+                }
+            },
+            LayerType::Image(image_layer) => {
+                if let Some(image) = &image_layer.image {
+                    if !image.source.exists() {
+                        failures.push(format!("layer {}: {}", layer.name, missing("texture", &image.source)));
+                    }
+                }
+            },
+            _ => ()
+        }
+    }
+
+    failures
+}
+
+/// Checks every map under `res/maps/`, printing a report<br>
+/// Returns how many failures were found
+pub fn check_content() -> Result<usize, String> {
+    let maps = loader::map_files(Path::new(loader::MAPS_DIR)).map_err(|e| format!("could not read {}: {}", loader::MAPS_DIR, e))?;
+
+    // The parsers panic on a lot of bad input, those panics are reported as failures instead
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut count = 0;
+    let mut failed_maps = 0;
+    for map in maps.iter() {
+        let failures = check_map(map);
+        if failures.is_empty() {
+            println!("ok      {}", map.display());
+        } else {
+            println!("FAILED  {}", map.display());
+            for failure in failures.iter() {
+                println!("        {}", failure);
+            }
+            count += failures.len();
+            failed_maps += 1;
+        }
+    }
+
+    panic::set_hook(hook);
+
+    println!("\nChecked {} maps, {} failed with {} problem{}", maps.len(), failed_maps, count, if count == 1 { "" } else { "s" });
+    Ok(count)
+}
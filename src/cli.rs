@@ -0,0 +1,173 @@
+use std::{fs, path::PathBuf};
+
+use crate::{check, headless::{HeadlessContext, Simulation}, lint, paths, replay::Recording, save::{SaveData, SaveInfo}, warps::WarpGraph};
+
+const USAGE: &str = "usage:
+    yume [--data-dir <dir>] [--seed <n>] [--record <file> | --replay <file>]
+                                            run the game, keeping saves and config in <dir>
+                                            --seed replays the same randomness, the seed is printed on start
+                                            --record writes every key press to <file> on exit, --replay plays one back
+    yume --export-save <slot> <file.json>   convert saves/<slot>.save to json
+    yume --import-save <file.json> <slot>   convert json back into saves/<slot>.save
+    yume --lint [map.tmx...]                check object json in the given maps, or every map in res/maps/
+    yume --check                            load every map and the files it uses, reporting failures
+    yume --warp-graph <dot|json> [file]     write how maps connect through warps, flagging unreachable maps and missing targets
+    yume --simulate <map.tmx> <ticks> [recording]
+                                            run a map without a window or audio device and print where the player ends up,
+                                            pressing the keys from a recording made with --record";
+
+/// Removes `name` and the value after it from `args`, returning the value
+pub fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    if index + 1 >= args.len() {
+        eprintln!("Warning: {} needs a value", name);
+        args.remove(index);
+        return None;
+    }
+
+    args.remove(index);
+    Some(args.remove(index))
+}
+
+/// Removes `--seed <n>` from `args`, returning the seed
+pub fn take_seed(args: &mut Vec<String>) -> Option<u64> {
+    let seed = take_option(args, "--seed")?;
+    match seed.parse::<u64>() {
+        Ok(seed) => Some(seed),
+        Err(_) => {
+            eprintln!("Warning: \"{}\" is not a seed, using a random one", seed);
+            None
+        }
+    }
+}
+
+fn parse_slot(slot: &str) -> Result<u32, String> {
+    slot.parse::<u32>().map_err(|_| format!("\"{}\" is not a save slot number", slot))
+}
+
+/// Runs a command line tool instead of the game if one was requested<br>
+/// Returns the exit code when a tool ran
+pub fn run(args: &[String], seed: Option<u64>) -> Option<i32> {
+    let result = match args.get(1).map(|arg| arg.as_str()) {
+        Some("--export-save") => export_save(&args[2..]),
+        Some("--import-save") => import_save(&args[2..]),
+        Some("--lint") => lint_maps(&args[2..]),
+        Some("--check") => check_content(),
+        Some("--warp-graph") => warp_graph(&args[2..]),
+        Some("--simulate") => simulate(&args[2..], seed),
+        Some("--help") => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        _ => return None
+    };
+
+    match result {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Some(1)
+        }
+    }
+}
+
+fn export_save(args: &[String]) -> Result<(), String> {
+    if args.len() != 2 {
+        return Err(USAGE.to_string());
+    }
+
+    let slot = parse_slot(&args[0])?;
+    let data = SaveData::load(&paths::save_slot(slot)).map_err(|e| e.to_string())?;
+    data.to_json(&PathBuf::from(&args[1])).map_err(|e| format!("could not write {}: {}", args[1], e))?;
+    println!("Exported slot {} to {}", slot, args[1]);
+
+    Ok(())
+}
+
+fn import_save(args: &[String]) -> Result<(), String> {
+    if args.len() != 2 {
+        return Err(USAGE.to_string());
+    }
+
+    let data = SaveData::from_json(&PathBuf::from(&args[0]))?;
+    let slot = parse_slot(&args[1])?;
+    let mut save_info = SaveInfo::read_or_create_new().map_err(|e| format!("could not read save index: {}", e))?;
+    data.save(slot, &paths::save_slot(slot), &mut save_info).map_err(|e| format!("could not write slot {}: {}", slot, e))?;
+    println!("Imported {} into slot {}", args[0], slot);
+
+    Ok(())
+}
+
+fn check_content() -> Result<(), String> {
+    let failures = check::check_content()?;
+    if failures > 0 {
+        return Err(format!("content check failed with {} problem{}", failures, if failures == 1 { "" } else { "s" }));
+    }
+
+    Ok(())
+}
+
+fn lint_maps(args: &[String]) -> Result<(), String> {
+    let maps = args.iter().map(PathBuf::from).collect::<Vec<PathBuf>>();
+    let problems = lint::lint_maps(&maps)?;
+    if problems > 0 {
+        return Err(format!("found {} problem{}", problems, if problems == 1 { "" } else { "s" }));
+    }
+    println!("No problems found");
+
+    Ok(())
+}
+
+fn warp_graph(args: &[String]) -> Result<(), String> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(USAGE.to_string());
+    }
+
+    let graph = WarpGraph::build()?;
+    let output = match args[0].as_str() {
+        "dot" => graph.to_dot(),
+        "json" => graph.to_json().pretty(4),
+        other => return Err(format!("unknown graph format \"{}\", expected dot or json", other))
+    };
+
+    match args.get(1) {
+        Some(file) => fs::write(file, output).map_err(|e| format!("could not write {}: {}", file, e))?,
+        None => print!("{}", output)
+    }
+
+    for map in graph.unreachable() {
+        eprintln!("Warning: {} can't be reached from {}", map, graph.start);
+    }
+    let missing = graph.missing_targets();
+    for warp in missing.iter() {
+        eprintln!("Warning: {} {} warps to missing map {}", warp.from, warp.source, warp.to);
+    }
+    if !missing.is_empty() {
+        return Err(format!("{} warp{} to missing maps", missing.len(), if missing.len() == 1 { " points" } else { "s point" }));
+    }
+
+    Ok(())
+}
+
+fn simulate(args: &[String], seed: Option<u64>) -> Result<(), String> {
+    if args.len() != 2 && args.len() != 3 {
+        return Err(USAGE.to_string());
+    }
+
+    let ticks = args[1].parse::<u32>().map_err(|_| format!("\"{}\" is not a number of ticks", args[1]))?;
+    let recording = args.get(2).map(|path| Recording::load(&PathBuf::from(path))).transpose()?;
+    let seed = match &recording {
+        Some(recording) => recording.seed,
+        None => seed.unwrap_or_else(rand::random)
+    };
+    println!("Random seed: {}", seed);
+    let context = HeadlessContext::new()?;
+    let mut simulation = Simulation::new(&context, &args[0], seed)?;
+    if let Some(recording) = recording {
+        simulation.replay(recording);
+    }
+    simulation.run(ticks.saturating_sub(simulation.ticks as u32));
+    println!("After {} ticks: map {}, player at tile ({}, {})", simulation.ticks, simulation.world.name, simulation.player.x / 16, simulation.player.y / 16);
+
+    Ok(())
+}
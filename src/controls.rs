@@ -0,0 +1,143 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use json::JsonValue;
+use sdl2::keyboard::Keycode;
+
+/// Something the player can do, bound to one or more keys
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    Confirm,
+    Cancel,
+    /// Opens and closes the in-game menu
+    Menu,
+    Up,
+    Down,
+    Left,
+    Right,
+    Fullscreen,
+    /// Held together with another key for debug commands
+    Debug
+}
+
+pub const ALL_ACTIONS: [Action; 9] = [
+    Action::Confirm,
+    Action::Cancel,
+    Action::Menu,
+    Action::Up,
+    Action::Down,
+    Action::Left,
+    Action::Right,
+    Action::Fullscreen,
+    Action::Debug
+];
+
+impl Action {
+    /// The name used in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Confirm => "confirm",
+            Self::Cancel => "cancel",
+            Self::Menu => "menu",
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Fullscreen => "fullscreen",
+            Self::Debug => "debug"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        ALL_ACTIONS.iter().find(|action| action.name() == name).copied()
+    }
+
+    pub fn default_keys(&self) -> Vec<Keycode> {
+        match self {
+            Self::Confirm => vec![Keycode::Z],
+            Self::Cancel => vec![Keycode::X],
+            Self::Menu => vec![Keycode::X],
+            Self::Up => vec![Keycode::Up, Keycode::W],
+            Self::Down => vec![Keycode::Down, Keycode::S],
+            Self::Left => vec![Keycode::Left, Keycode::A],
+            Self::Right => vec![Keycode::Right, Keycode::D],
+            Self::Fullscreen => vec![Keycode::F4],
+            Self::Debug => vec![Keycode::F3, Keycode::LAlt]
+        }
+    }
+}
+
+/// Which keys trigger each action
+#[derive(Clone)]
+pub struct Bindings {
+    keys: HashMap<Action, Vec<Keycode>>
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            keys: ALL_ACTIONS.iter().map(|action| (*action, action.default_keys())).collect()
+        }
+    }
+}
+
+impl Bindings {
+    pub fn keys(&self, action: Action) -> &[Keycode] {
+        self.keys.get(&action).map(|keys| keys.as_slice()).unwrap_or(&[])
+    }
+
+    /// Reads the `controls` object of the config file, actions it leaves out keep their default keys<br>
+    /// Writes the defaults to a new config file if there isn't one
+    pub fn read_or_create(path: &Path) -> Self {
+        let mut bindings = Self::default();
+        if !path.exists() {
+            let mut config = JsonValue::new_object();
+            config["controls"] = bindings.to_json();
+            if let Err(e) = fs::write(path, config.pretty(4)) {
+                eprintln!("Warning: could not write {}: {}", path.display(), e);
+            }
+            return bindings;
+        }
+
+        let config = match fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|source| json::parse(&source).map_err(|e| e.to_string())) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Warning: could not read {}, using the default controls: {}", path.display(), e);
+                return bindings;
+            }
+        };
+
+        for (name, keys) in config["controls"].entries() {
+            let action = match Action::from_name(name) {
+                Some(action) => action,
+                None => {
+                    eprintln!("Warning: {}: unknown action \"{}\"", path.display(), name);
+                    continue;
+                }
+            };
+            if !keys.is_array() {
+                eprintln!("Warning: {}: controls.{} should be a list of key names", path.display(), name);
+                continue;
+            }
+
+            let mut bound = Vec::new();
+            for key in keys.members() {
+                match key.as_str().and_then(Keycode::from_name) {
+                    Some(keycode) => bound.push(keycode),
+                    None => eprintln!("Warning: {}: unknown key {} bound to {}", path.display(), key, name)
+                }
+            }
+            bindings.keys.insert(action, bound);
+        }
+
+        bindings
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut controls = JsonValue::new_object();
+        for action in ALL_ACTIONS.iter() {
+            controls[action.name()] = self.keys(*action).iter().map(|key| key.name()).collect::<Vec<String>>().into();
+        }
+
+        controls
+    }
+}
@@ -1,9 +1,9 @@
 use std::{collections::{HashMap, LinkedList}, path::PathBuf, sync::LazyLock, thread::{self, JoinHandle}, time::{Duration, Instant}};
 
 use rfd::FileDialog;
-use sdl2::{keyboard::Keycode, render::{Canvas, RenderTarget}};
+use sdl2::{keyboard::Keycode, pixels::Color, rect::Rect, render::{Canvas, RenderTarget}};
 
-use crate::{audio::SoundEffectBank, effect, game::{Input, IntProperty, LevelPropertyType, RenderState, WarpPos}, player::Player, transitions::{Transition, TransitionType}, ui::{Font, Ui}, world::World};
+use crate::{audio::SoundEffectBank, controls::Action, effect, game::{Input, IntProperty, LevelPropertyType, RenderState, WarpPos}, player::Player, transitions::{Transition, TransitionType}, ui::{Font, Ui}, world::World};
 
 #[derive(PartialEq, Eq, Hash, Debug)]
 pub enum ProfileTargetType {
@@ -35,6 +35,8 @@ impl ProfileTarget {
 
 const FRAME_AVG_SAMPLE: usize = 100;
 const SPIKE_LIMIT: u32 = 10;
+/// Most lines of script errors shown on screen at once
+const SCRIPT_ERROR_LINES: usize = 12;
 
 pub struct ProfileInfo {
     stages: HashMap<ProfileTargetType, ProfileTarget>,
@@ -169,9 +171,9 @@ pub struct Debug<'a> {
     pub mini_font: Font<'a>
 }
 
+/// `key` pressed while holding the debug action, F3 or left alt by default
 fn f3_combo(input: &Input, key: Keycode) -> bool {
-    input.get_pressed(Keycode::F3) && input.get_just_pressed(key)
-    || input.get_pressed(Keycode::LAlt) && input.get_just_pressed(key)
+    input.get_action_pressed(Action::Debug) && input.get_just_pressed(key)
 }
 
 impl<'a> Debug<'a> {
@@ -311,7 +313,7 @@ impl<'a> Debug<'a> {
         }
     }
 
-    pub fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, ui: &Ui, player: &Player, state: &RenderState) {
+    pub fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, ui: &Ui, player: &Player, state: &RenderState, script_errors: &Vec<String>) {
         if self.enable_profiling {
             self.profiler.past_frames.push_front(self.profiler.get_stage_timing(&ProfileTargetType::Frame).unwrap_or(Duration::ZERO));
             if self.profiler.past_frames.len() >= FRAME_AVG_SAMPLE {
@@ -359,5 +361,37 @@ impl<'a> Debug<'a> {
             ui.theme.font.draw_string(canvas, "The Quick Brown Fox Jumped Over The Lazy Dog", (10, state.screen_extents.1 as i32 - 35));
             ui.theme.font.draw_string(canvas, "THE QUICK BROWN FOX JUMPED OVER THE LAZY DOG", (10, state.screen_extents.1 as i32 - 20));
         }
+
+        if crate::DEBUG && !script_errors.is_empty() {
+            self.draw_script_errors(canvas, state, script_errors);
+        }
+    }
+
+    /// Shows the most recent script errors along the bottom of the screen
+    fn draw_script_errors<T: RenderTarget>(&self, canvas: &mut Canvas<T>, state: &RenderState, script_errors: &Vec<String>) {
+        let spacing_x = self.mini_font.char_width + self.mini_font.char_spacing.0;
+        let line_height = (self.mini_font.char_height + self.mini_font.char_spacing.1) as i32;
+        let max_chars = ((state.screen_extents.0 - 8) / spacing_x).max(1) as usize;
+
+        // Split tracebacks into lines and wrap anything too wide for the screen
+        let mut lines = Vec::new();
+        for error in script_errors.iter() {
+            for line in error.lines() {
+                let chars = line.replace('\t', "  ").chars().collect::<Vec<char>>();
+                for chunk in chars.chunks(max_chars) {
+                    lines.push(chunk.iter().collect::<String>());
+                }
+            }
+        }
+        let shown = &lines[lines.len().saturating_sub(SCRIPT_ERROR_LINES)..];
+
+        let height = shown.len() as i32 * line_height + 8;
+        let top = state.screen_extents.1 as i32 - height;
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 200));
+        canvas.fill_rect(Rect::new(0, top, state.screen_extents.0, height as u32)).unwrap();
+
+        for (i, line) in shown.iter().enumerate() {
+            self.mini_font.draw_string(canvas, line, (4, top + 4 + i as i32 * line_height));
+        }
     }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, rc::Rc, cell::RefCell};
+use std::{collections::HashMap, rc::Rc, cell::RefCell, path::PathBuf};
 
 use sdl2::rect::Rect;
 
@@ -235,7 +235,8 @@ pub struct Entity {
     pub variables: Rc<RefCell<HashMap<String, VariableValue>>>,
     pub particle_emitter: Option<ParticleEmitter>,
     pub killable: bool,
-    pub script: Option<String>
+    /// Path to the entity's lua script
+    pub script: Option<PathBuf>
 }
 
 // TODO looping movement for entities
@@ -422,7 +423,7 @@ impl Entity {
         }
 
         if let Some(particle_emitter) = &mut self.particle_emitter {
-            particle_emitter.update((self.x, self.y));
+            particle_emitter.update((self.x, self.y), &mut world.random.effects);
         }
 
         if let Some(movement) = &mut self.movement {
@@ -4,7 +4,7 @@ use json::JsonValue;
 use rand::{prelude::Distribution, distributions::Standard};
 use sdl2::keyboard::Keycode;
 
-use crate::{effect::Effect, player::Player, world::{Axis, World}};
+use crate::{controls::{Action, Bindings}, effect::Effect, player::Player, world::{Axis, World}};
 
 pub fn offset_floor(n: i32, to: i32, offset: i32) -> i32 {
     (n as f32 / to as f32).floor() as i32 * to + (offset.abs() % to)
@@ -923,24 +923,22 @@ impl Direction {
         }
     }
 
-    pub fn from_key(key: &Keycode) -> Option<Self> {
-        use Keycode::*;
-        match key {
-            Up | W => Some(Self::Up),
-            Left | A => Some(Self::Left),
-            Right | D => Some(Self::Right),
-            Down | S => Some(Self::Down),
-            _ => None
+    /// The action that walks in this direction
+    pub fn action(&self) -> Action {
+        match *self {
+            Self::Up => Action::Up,
+            Self::Down => Action::Down,
+            Self::Left => Action::Left,
+            Self::Right => Action::Right
         }
     }
 
-    pub fn to_key(&self) -> Option<Keycode> {
-        use Keycode::*;
-        match *self {
-            Self::Up => Some(Up),
-            Self::Down => Some(Down),
-            Self::Left => Some(Left),
-            Self::Right => Some(Right),
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Left => "left",
+            Self::Right => "right"
         }
     }
 
@@ -992,13 +990,15 @@ pub enum KeyState {
 }
 
 pub struct Input {
-    pub keys: HashMap<Keycode, KeyState>
+    pub keys: HashMap<Keycode, KeyState>,
+    pub bindings: Bindings
 }
 
 impl Input {
     pub fn new() -> Self {
         Self {
-            keys: HashMap::new()
+            keys: HashMap::new(),
+            bindings: Bindings::default()
         }
     }
 
@@ -1040,6 +1040,16 @@ impl Input {
     pub fn get_keystate(&self, key: Keycode) -> KeyState {
         *self.keys.get(&key).unwrap_or(&KeyState::Released)
     }
+
+    /// Returns true if any key bound to `action` is pressed
+    pub fn get_action_pressed(&self, action: Action) -> bool {
+        self.bindings.keys(action).iter().any(|key| self.get_pressed(*key))
+    }
+
+    /// Returns true if any key bound to `action` has just been pressed
+    pub fn get_action_just_pressed(&self, action: Action) -> bool {
+        self.bindings.keys(action).iter().any(|key| self.get_just_pressed(*key))
+    }
 }
 
 pub struct RenderState {
@@ -0,0 +1,207 @@
+use rodio::{queue::SourcesQueueOutput, Sink};
+use sdl2::{keyboard::Keycode, pixels::PixelFormatEnum, render::{Canvas, TextureCreator}, surface::{Surface, SurfaceContext}};
+
+use crate::{audio::SoundEffectBank, entity::Entity, game::{Direction, Input, RenderState}, lua::ScriptingContext, player::Player, replay::{Recording, Replay}, world::{RandomState, World}};
+
+/// How many ticks a single step may take before the simulation gives up on it
+const STEP_TICK_LIMIT: u32 = 600;
+
+/// One step of scripted input
+pub enum Step {
+    /// Hold a key down until it is released
+    Press(Keycode),
+    Release(Keycode),
+    /// Press a key for one tick
+    Tap(Keycode),
+    /// Hold a direction until the player has walked this many tiles, then wait for them to stop
+    Walk(Direction, u32),
+    Wait(u32)
+}
+
+/// Stands in for the window and audio device so a world can run without either<br>
+/// Textures go to a software renderer drawing on a surface, music goes to a sink nothing reads from
+pub struct HeadlessContext {
+    pub creator: TextureCreator<SurfaceContext<'static>>,
+    pub canvas: Canvas<Surface<'static>>,
+    pub sink: Sink,
+    _queue: SourcesQueueOutput<f32>
+}
+
+impl HeadlessContext {
+    pub fn new() -> Result<Self, String> {
+        let surface = Surface::new(640, 480, PixelFormatEnum::RGBA8888)?;
+        let canvas = surface.into_canvas()?;
+        let (sink, queue) = Sink::new_idle();
+
+        Ok(Self {
+            creator: canvas.texture_creator(),
+            canvas,
+            sink,
+            _queue: queue
+        })
+    }
+}
+
+/// A world and player ticked the same way the main loop does, minus the menus and drawing
+pub struct Simulation<'a> {
+    pub world: World<'a>,
+    pub player: Player<'a>,
+    pub input: Input,
+    pub scripts: ScriptingContext,
+    pub sfx: SoundEffectBank,
+    pub render_state: RenderState,
+    pub ticks: u64,
+    /// Key events sent by `play` are added here when set
+    pub recording: Option<Recording>,
+    context: &'a HeadlessContext
+}
+
+impl<'a> Simulation<'a> {
+    /// Loads `map` with a fresh player at the map's default position<br>
+    /// The same seed and steps always end in the same state
+    pub fn new(context: &'a HeadlessContext, map: &str, seed: u64) -> Result<Self, String> {
+        let render_state = RenderState::new((640, 480));
+        let mut player = Player::new(&context.creator);
+        let mut scripts = ScriptingContext::new();
+        let mut world = World::load_from_file(&map.to_string(), &context.creator, &mut None, &render_state).map_err(|e| format!("failed to load {}: {}", map, e))?;
+        world.random = RandomState::new(seed);
+
+        world.onload(&player, &context.sink, &render_state, &mut scripts);
+        scripts.on_load(&mut world, &mut player);
+        if let Some(def) = world.default_pos {
+            player.set_x(def.0 * 16);
+            player.set_y(def.1 * 16);
+        }
+
+        Ok(Self {
+            world,
+            player,
+            input: Input::new(),
+            scripts,
+            sfx: SoundEffectBank::silent(),
+            render_state,
+            ticks: 0,
+            recording: None,
+            context
+        })
+    }
+
+    /// Runs one frame of game logic
+    pub fn tick(&mut self) {
+        if !self.world.paused {
+            self.player.update(&self.input, &mut self.world, &mut self.sfx);
+            self.player.stats.playtime += crate::TICK_INTERVAL as u64;
+        }
+        self.world.update(&mut self.player, &mut self.sfx, &self.context.sink, &self.input, &mut self.render_state, &mut self.scripts);
+        if self.player.effect_just_changed {
+            self.player.effect_just_changed = false;
+        }
+
+        self.scripts.on_update(&mut self.world, &mut self.player, &self.input);
+
+        self.input.update();
+        crate::clamp_camera(&mut self.render_state, &self.world, &self.player);
+
+        // Nothing is drawn, so screenshots are never taken
+        self.world.transition_context.take_screenshot = false;
+
+        if self.world.queued_load.is_some() && self.world.transition.as_ref().is_some_and(|transition| transition.progress >= 100) {
+            let world = std::mem::replace(&mut self.world, World::new(&self.context.creator, &self.render_state));
+            let (loaded, _) = crate::load_queued_map(world, &mut self.player, &mut self.scripts, &self.context.sink, &self.render_state, &self.context.creator);
+            self.world = loaded;
+        }
+
+        self.ticks += 1;
+    }
+
+    pub fn run(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    pub fn press(&mut self, key: Keycode) {
+        self.input.pressed(key);
+        if let Some(recording) = &mut self.recording {
+            recording.record(self.ticks, key, true);
+        }
+    }
+
+    pub fn release(&mut self, key: Keycode) {
+        self.input.released(key);
+        if let Some(recording) = &mut self.recording {
+            recording.record(self.ticks, key, false);
+        }
+    }
+
+    pub fn play(&mut self, steps: &[Step]) {
+        for step in steps.iter() {
+            match step {
+                Step::Press(key) => self.press(*key),
+                Step::Release(key) => self.release(*key),
+                Step::Tap(key) => {
+                    self.press(*key);
+                    self.tick();
+                    self.release(*key);
+                },
+                Step::Walk(direction, tiles) => self.walk(*direction, *tiles),
+                Step::Wait(ticks) => self.run(*ticks)
+            }
+        }
+    }
+
+    /// Panics if the player is blocked before reaching the tile
+    pub fn walk(&mut self, direction: Direction, tiles: u32) {
+        let key = *self.input.bindings.keys(direction.action()).first().expect("no key bound to walk in that direction");
+        let start = self.player.occupied_tile;
+        let target = (
+            (start.0 as i32 + direction.x() * tiles as i32) as u32,
+            (start.1 as i32 + direction.y() * tiles as i32) as u32
+        );
+
+        // Let go once the last step has started, holding on would start another one
+        self.press(key);
+        let mut ticks = 0;
+        while self.player.occupied_tile != target {
+            self.tick();
+            ticks += 1;
+            assert!(ticks < STEP_TICK_LIMIT, "player walking {:?} from {:?} got stuck at {:?} before {:?}", direction, start, self.player.occupied_tile, target);
+        }
+        self.release(key);
+
+        while self.player.moving {
+            self.tick();
+            ticks += 1;
+            assert!(ticks < STEP_TICK_LIMIT, "player never stopped walking");
+        }
+    }
+
+    /// Plays back a recording up to its last event<br>
+    /// Recorded ticks count from the start of the simulation, menus aren't simulated so recordings
+    /// from the game only line up if they start in the map
+    pub fn replay(&mut self, recording: Recording) {
+        let mut replay = Replay::new(recording);
+        while !replay.finished() {
+            replay.apply(self.ticks, &mut self.input);
+            self.tick();
+        }
+    }
+
+    /// The player's position in tiles, the same way warps place them
+    pub fn tile(&self) -> (i32, i32) {
+        (self.player.x / 16, self.player.y / 16)
+    }
+
+    pub fn flag(&self, name: &str) -> Option<i32> {
+        self.world.flags.get(name).copied()
+    }
+
+    pub fn global_flag(&self, name: &str) -> Option<i32> {
+        self.world.global_flags.get(name).copied()
+    }
+
+    /// The first entity standing on a tile
+    pub fn entity_at(&self, x: i32, y: i32) -> Option<&Entity> {
+        self.world.entities.as_ref().unwrap().iter().find(|entity| entity.x / 16 == x && entity.y / 16 == y)
+    }
+}
@@ -0,0 +1,709 @@
+use std::{collections::HashMap, path::{Path, PathBuf}};
+
+use json::JsonValue;
+use tiled::{LayerType, Loader, PropertyValue};
+
+use crate::{effect::Effect, game::Direction, loader};
+
+/// Checks the json properties of map objects for unknown keys, wrong types and missing fields<br>
+/// Every problem is reported with a json path like `actions[1].action.condition.lhs`
+pub struct Lint {
+    pub problems: Vec<String>
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+fn index(path: &str, i: usize) -> String {
+    format!("{}[{}]", path, i)
+}
+
+fn kind_name(json: &JsonValue) -> &'static str {
+    match json {
+        JsonValue::Null => "null",
+        JsonValue::Short(_) | JsonValue::String(_) => "string",
+        JsonValue::Number(_) => "number",
+        JsonValue::Boolean(_) => "bool",
+        JsonValue::Object(_) => "object",
+        JsonValue::Array(_) => "array"
+    }
+}
+
+const PLAYER_PROPERTIES: [&str; 6] = ["x", "y", "height", "dreaming", "layer", "check_walkable"];
+const ENTITY_PROPERTIES: [&str; 4] = ["x", "y", "id", "draw"];
+const LEVEL_PROPERTIES: [&str; 13] = [
+    "default_x", "default_y", "tint_r", "tint_g", "tint_b", "tint_a", "special_save_game", "special_music_menu",
+    "paused", "background_r", "background_g", "background_b", "new_session"
+];
+/// Triggers that work as a plain string, `tick` only works as `{ "type": "tick", "freq": n }`
+const TRIGGERS: [&str; 6] = ["use", "walk", "bump", "interact", "onload", "switch"];
+const TRANSITIONS: [&str; 11] = ["fade", "fade_to_color", "music_only", "spotlight", "spin", "zoom", "pixelate", "lines", "wave", "grid_cycle", "player_fall"];
+const PATHFINDERS: [&str; 6] = ["astar", "a_star", "a*", "walk_towards", "walktowards", "erratic"];
+const RANDOM_SOURCES: [&str; 5] = ["level", "world", "pure", "session", "save"];
+
+impl Lint {
+    pub fn new() -> Self {
+        Self {
+            problems: Vec::new()
+        }
+    }
+
+    fn report(&mut self, path: &str, message: String) {
+        if path.is_empty() {
+            self.problems.push(message);
+        } else {
+            self.problems.push(format!("{}: {}", path, message));
+        }
+    }
+
+    /// Reports any key of `json` that isn't in `allowed`
+    fn keys(&mut self, json: &JsonValue, path: &str, allowed: &[&str]) {
+        for (key, _) in json.entries() {
+            if !allowed.contains(&key) {
+                self.report(&join(path, key), "unknown key".to_string());
+            }
+        }
+    }
+
+    /// Reports a missing field, returns true if it is there
+    fn required(&mut self, json: &JsonValue, path: &str, key: &str) -> bool {
+        if json[key].is_null() {
+            self.report(&join(path, key), "missing required field".to_string());
+            return false;
+        }
+        true
+    }
+
+    fn wrong_type(&mut self, json: &JsonValue, path: &str, expected: &str) {
+        self.report(path, format!("expected {}, found {}", expected, kind_name(json)));
+    }
+
+    fn string(&mut self, json: &JsonValue, path: &str) {
+        if !json.is_null() && !json.is_string() {
+            self.wrong_type(json, path, "a string");
+        }
+    }
+
+    fn number(&mut self, json: &JsonValue, path: &str) {
+        if !json.is_null() && !json.is_number() {
+            self.wrong_type(json, path, "a number");
+        }
+    }
+
+    fn unsigned(&mut self, json: &JsonValue, path: &str) {
+        if !json.is_null() && json.as_u32().is_none() {
+            self.wrong_type(json, path, "a positive whole number");
+        }
+    }
+
+    fn boolean(&mut self, json: &JsonValue, path: &str) {
+        if !json.is_null() && !json.is_boolean() {
+            self.wrong_type(json, path, "a bool");
+        }
+    }
+
+    /// Reports a string that isn't one of `options`
+    fn one_of(&mut self, json: &JsonValue, path: &str, options: &[&str]) {
+        if json.is_null() { return; }
+        match json.as_str() {
+            Some(value) if options.contains(&value) => (),
+            Some(value) => self.report(path, format!("\"{}\" is not one of {}", value, options.join(", "))),
+            None => self.wrong_type(json, path, "a string")
+        }
+    }
+
+    fn direction(&mut self, json: &JsonValue, path: &str) {
+        if json.is_null() { return; }
+        match json.as_str() {
+            Some(value) if value.parse::<Direction>().is_ok() => (),
+            Some(value) => self.report(path, format!("\"{}\" is not a direction", value)),
+            None => self.wrong_type(json, path, "a direction string")
+        }
+    }
+
+    /// Reads the `type` of a tagged object, reporting it if it is missing
+    fn tag<'j>(&mut self, json: &'j JsonValue, path: &str) -> Option<&'j str> {
+        if !json.is_object() {
+            self.wrong_type(json, path, "an object");
+            return None;
+        }
+        match json["type"].as_str() {
+            Some(tag) => Some(tag),
+            None => {
+                self.required(json, path, "type");
+                None
+            }
+        }
+    }
+
+    fn lhs_rhs(&mut self, json: &JsonValue, path: &str, check: fn(&mut Self, &JsonValue, &str)) {
+        self.keys(json, path, &["type", "lhs", "rhs"]);
+        if self.required(json, path, "lhs") { check(self, &json["lhs"], &join(path, "lhs")); }
+        if self.required(json, path, "rhs") { check(self, &json["rhs"], &join(path, "rhs")); }
+    }
+
+    fn property_name(&mut self, json: &JsonValue, path: &str, options: &[&str]) {
+        if !self.required(json, path, "property") { return; }
+        let property = &json["property"];
+        let name = if property.is_object() { &property["type"] } else { property };
+        self.one_of(name, &join(path, "property"), options);
+    }
+
+    fn variable(&mut self, json: &JsonValue, path: &str) {
+        self.keys(json, path, &["type", "name"]);
+        if self.required(json, path, "name") { self.string_property(&json["name"], &join(path, "name")); }
+    }
+
+    pub fn int_property(&mut self, json: &JsonValue, path: &str) {
+        if json.is_number() { return; }
+        let tag = match self.tag(json, path) { Some(tag) => tag, None => return };
+        match tag {
+            "int" => {
+                self.keys(json, path, &["type", "val"]);
+                if self.required(json, path, "val") { self.number(&json["val"], &join(path, "val")); }
+            },
+            "player" => {
+                self.keys(json, path, &["type", "property"]);
+                self.property_name(json, path, &PLAYER_PROPERTIES);
+            },
+            "entity" => {
+                self.keys(json, path, &["type", "property"]);
+                self.property_name(json, path, &ENTITY_PROPERTIES);
+            },
+            "level" => {
+                self.keys(json, path, &["type", "property"]);
+                self.property_name(json, path, &LEVEL_PROPERTIES);
+            },
+            "flag" => {
+                self.keys(json, path, &["type", "flag", "global"]);
+                if self.required(json, path, "flag") { self.string_property(&json["flag"], &join(path, "flag")); }
+                self.boolean(&json["global"], &join(path, "global"));
+            },
+            "add" | "sub" | "mul" | "div" => self.lhs_rhs(json, path, Self::int_property),
+            "variable" | "var" => self.variable(json, path),
+            other => self.report(&join(path, "type"), format!("unknown int property type \"{}\"", other))
+        }
+    }
+
+    pub fn float_property(&mut self, json: &JsonValue, path: &str) {
+        if json.is_number() { return; }
+        let tag = match self.tag(json, path) { Some(tag) => tag, None => return };
+        match tag {
+            "float" => {
+                self.keys(json, path, &["type", "val"]);
+                if self.required(json, path, "val") { self.number(&json["val"], &join(path, "val")); }
+            },
+            "player" => {
+                self.keys(json, path, &["type", "property"]);
+                self.property_name(json, path, &PLAYER_PROPERTIES);
+            },
+            "level" => {
+                self.keys(json, path, &["type", "property"]);
+                self.property_name(json, path, &LEVEL_PROPERTIES);
+            },
+            "add" | "sub" | "mul" | "div" => self.lhs_rhs(json, path, Self::float_property),
+            "variable" | "var" => self.variable(json, path),
+            other => self.report(&join(path, "type"), format!("unknown float property type \"{}\"", other))
+        }
+    }
+
+    pub fn bool_property(&mut self, json: &JsonValue, path: &str) {
+        if json.is_boolean() { return; }
+        let tag = match self.tag(json, path) { Some(tag) => tag, None => return };
+        match tag {
+            "bool" => {
+                self.keys(json, path, &["type", "val"]);
+                if self.required(json, path, "val") { self.boolean(&json["val"], &join(path, "val")); }
+            },
+            "player" => {
+                self.keys(json, path, &["type", "property"]);
+                self.property_name(json, path, &PLAYER_PROPERTIES);
+            },
+            "level" => {
+                self.keys(json, path, &["type", "property"]);
+                self.property_name(json, path, &LEVEL_PROPERTIES);
+            },
+            "and" | "or" | "xor" => self.lhs_rhs(json, path, Self::bool_property),
+            "not" => {
+                self.keys(json, path, &["type", "val"]);
+                if self.required(json, path, "val") { self.bool_property(&json["val"], &join(path, "val")); }
+            },
+            "variable" | "var" => self.variable(json, path),
+            "condition" | "from_condition" | "conditional" => {
+                self.keys(json, path, &["type", "condition"]);
+                if self.required(json, path, "condition") { self.condition(&json["condition"], &join(path, "condition")); }
+            },
+            other => self.report(&join(path, "type"), format!("unknown bool property type \"{}\"", other))
+        }
+    }
+
+    pub fn string_property(&mut self, json: &JsonValue, path: &str) {
+        if json.is_string() { return; }
+        let tag = match self.tag(json, path) { Some(tag) => tag, None => return };
+        match tag {
+            "string" => {
+                self.keys(json, path, &["type", "val"]);
+                if self.required(json, path, "val") { self.string(&json["val"], &join(path, "val")); }
+            },
+            "from_int" => {
+                self.keys(json, path, &["type", "val"]);
+                if self.required(json, path, "val") { self.int_property(&json["val"], &join(path, "val")); }
+            },
+            "concatenate" => self.lhs_rhs(json, path, Self::string_property),
+            "variable" | "var" => self.variable(json, path),
+            other => self.report(&join(path, "type"), format!("unknown string property type \"{}\"", other))
+        }
+    }
+
+    pub fn condition(&mut self, json: &JsonValue, path: &str) {
+        let tag = match self.tag(json, path) { Some(tag) => tag, None => return };
+        match tag {
+            "int_equals" | "int_greater" | "int_less" => self.lhs_rhs(json, path, Self::int_property),
+            "string_equals" => self.lhs_rhs(json, path, Self::string_property),
+            "effect_equipped" => {
+                self.keys(json, path, &["type", "effect"]);
+                if self.required(json, path, "effect") { self.effect(&json["effect"], &join(path, "effect")); }
+            },
+            "negate" => {
+                self.keys(json, path, &["type", "condition"]);
+                if self.required(json, path, "condition") { self.condition(&json["condition"], &join(path, "condition")); }
+            },
+            "bool" => {
+                self.keys(json, path, &["type", "val"]);
+                if self.required(json, path, "val") { self.bool_property(&json["val"], &join(path, "val")); }
+            },
+            "variable" | "var" => self.variable(json, path),
+            other => self.report(&join(path, "type"), format!("unknown condition type \"{}\"", other))
+        }
+    }
+
+    fn effect(&mut self, json: &JsonValue, path: &str) {
+        match json.as_str() {
+            Some(effect) if Effect::parse(effect).is_some() => (),
+            Some(effect) => self.report(path, format!("unknown effect \"{}\"", effect)),
+            None => self.wrong_type(json, path, "an effect name")
+        }
+    }
+
+    fn warp_pos(&mut self, json: &JsonValue, path: &str) {
+        if !json.is_object() {
+            self.wrong_type(json, path, "an object");
+            return;
+        }
+        self.keys(json, path, &["x", "y"]);
+        if self.required(json, path, "x") { self.int_property(&json["x"], &join(path, "x")); }
+        if self.required(json, path, "y") { self.int_property(&json["y"], &join(path, "y")); }
+    }
+
+    fn transition(&mut self, json: &JsonValue, path: &str) {
+        if json.is_null() { return; }
+        if json.is_string() {
+            self.one_of(json, path, &TRANSITIONS);
+            return;
+        }
+        let tag = match self.tag(json, path) { Some(tag) => tag, None => return };
+        self.one_of(&json["type"], &join(path, "type"), &TRANSITIONS);
+        let mut allowed = vec!["type", "speed", "music", "hold", "reset_music"];
+        match tag {
+            "zoom" => allowed.push("scale"),
+            "lines" => allowed.push("height"),
+            "wave" => allowed.extend(["dir", "waves"]),
+            "fade_to_color" => {
+                allowed.extend(["r", "g", "b"]);
+                for channel in ["r", "g", "b"] {
+                    if self.required(json, path, channel) { self.unsigned(&json[channel], &join(path, channel)); }
+                }
+            },
+            _ => ()
+        }
+        self.keys(json, path, &allowed);
+        self.number(&json["speed"], &join(path, "speed"));
+        self.boolean(&json["music"], &join(path, "music"));
+        self.unsigned(&json["hold"], &join(path, "hold"));
+        self.boolean(&json["reset_music"], &join(path, "reset_music"));
+    }
+
+    /// Checks `"self"`/`"this"` or an entity id
+    fn entity_target(&mut self, json: &JsonValue, path: &str) {
+        if json.is_string() {
+            self.one_of(json, path, &["self", "this"]);
+        } else {
+            self.int_property(json, path);
+        }
+    }
+
+    pub fn action(&mut self, json: &JsonValue, path: &str) {
+        if json.is_array() {
+            for (i, action) in json.members().enumerate() {
+                self.action(action, &index(path, i));
+            }
+            return;
+        }
+
+        let tag = match self.tag(json, path) { Some(tag) => tag, None => return };
+        match tag {
+            "warp" => {
+                self.keys(json, path, &["type", "map", "transition", "pos"]);
+                self.string(&json["map"], &join(path, "map"));
+                self.transition(&json["transition"], &join(path, "transition"));
+                if self.required(json, path, "pos") { self.warp_pos(&json["pos"], &join(path, "pos")); }
+            },
+            "print" => {
+                self.keys(json, path, &["type", "message"]);
+                if self.required(json, path, "message") { self.string_property(&json["message"], &join(path, "message")); }
+            },
+            "delayed" => {
+                self.keys(json, path, &["type", "delay", "action"]);
+                if self.required(json, path, "delay") { self.unsigned(&json["delay"], &join(path, "delay")); }
+                if self.required(json, path, "action") { self.action(&json["action"], &join(path, "action")); }
+            },
+            "freeze" => {
+                self.keys(json, path, &["type", "time"]);
+                self.unsigned(&json["time"], &join(path, "time"));
+            },
+            "give_effect" => {
+                self.keys(json, path, &["type", "effect"]);
+                if self.required(json, path, "effect") { self.effect(&json["effect"], &join(path, "effect")); }
+            },
+            "set_flag" => {
+                self.keys(json, path, &["type", "flag", "val", "global"]);
+                if self.required(json, path, "flag") { self.string_property(&json["flag"], &join(path, "flag")); }
+                if self.required(json, path, "val") { self.int_property(&json["val"], &join(path, "val")); }
+                self.boolean(&json["global"], &join(path, "global"));
+            },
+            "conditional" => {
+                self.keys(json, path, &["type", "condition", "action"]);
+                if self.required(json, path, "condition") { self.condition(&json["condition"], &join(path, "condition")); }
+                if self.required(json, path, "action") { self.action(&json["action"], &join(path, "action")); }
+            },
+            "play" => {
+                self.keys(json, path, &["type", "sound", "speed", "volume"]);
+                if self.required(json, path, "sound") { self.string(&json["sound"], &join(path, "sound")); }
+                self.number(&json["speed"], &join(path, "speed"));
+                self.number(&json["volume"], &join(path, "volume"));
+            },
+            "set" => {
+                self.keys(json, path, &["type", "in", "val", "to"]);
+                self.required(json, path, "to");
+                if self.required(json, path, "in") && self.required(json, path, "val") {
+                    match json["in"].as_str() {
+                        Some("player") => self.one_of(&json["val"], &join(path, "val"), &PLAYER_PROPERTIES),
+                        Some("world") => self.one_of(&json["val"], &join(path, "val"), &LEVEL_PROPERTIES),
+                        Some("entity") => self.one_of(&json["val"], &join(path, "val"), &ENTITY_PROPERTIES),
+                        _ => self.one_of(&json["in"], &join(path, "in"), &["player", "world", "entity"])
+                    }
+                }
+            },
+            "change_song" => {
+                self.keys(json, path, &["type", "song", "speed", "volume", "set_defaults"]);
+                if !json["song"].is_null() { self.string_property(&json["song"], &join(path, "song")); }
+                if !json["speed"].is_null() { self.float_property(&json["speed"], &join(path, "speed")); }
+                if !json["volume"].is_null() { self.float_property(&json["volume"], &join(path, "volume")); }
+                if !json["set_defaults"].is_null() { self.bool_property(&json["set_defaults"], &join(path, "set_defaults")); }
+            },
+            "set_animation_frame" => {
+                self.keys(json, path, &["type", "val", "target"]);
+                if self.required(json, path, "val") { self.int_property(&json["val"], &join(path, "val")); }
+                if self.required(json, path, "target") { self.entity_target(&json["target"], &join(path, "target")); }
+            },
+            "multiple" => {
+                self.keys(json, path, &["type", "actions"]);
+                if self.required(json, path, "actions") {
+                    if json["actions"].is_array() {
+                        self.action(&json["actions"], &join(path, "actions"));
+                    } else {
+                        self.wrong_type(&json["actions"], &join(path, "actions"), "an array");
+                    }
+                }
+            },
+            "set_variable" | "set_var" => {
+                self.keys(json, path, &["type", "var_type", "name", "val", "store"]);
+                if self.required(json, path, "name") { self.string_property(&json["name"], &join(path, "name")); }
+                self.boolean(&json["store"], &join(path, "store"));
+                if self.required(json, path, "var_type") && self.required(json, path, "val") {
+                    let val_path = join(path, "val");
+                    match json["var_type"].as_str() {
+                        Some("int") => self.int_property(&json["val"], &val_path),
+                        Some("float") => self.float_property(&json["val"], &val_path),
+                        Some("bool") | Some("boolean") => self.bool_property(&json["val"], &val_path),
+                        Some("string") => self.string_property(&json["val"], &val_path),
+                        _ => self.one_of(&json["var_type"], &join(path, "var_type"), &["int", "float", "bool", "boolean", "string"])
+                    }
+                }
+            },
+            "sit" | "lay_down" | "unfreeze" => self.keys(json, path, &["type"]),
+            "remove" => {
+                self.keys(json, path, &["type", "target"]);
+                if self.required(json, path, "target") { self.entity_target(&json["target"], &join(path, "target")); }
+            },
+            "lay_down_in_place" => {
+                self.keys(json, path, &["type", "exit_dir", "offset_x", "offset_y"]);
+                self.direction(&json["exit_dir"], &join(path, "exit_dir"));
+                if !json["offset_x"].is_null() { self.int_property(&json["offset_x"], &join(path, "offset_x")); }
+                if !json["offset_y"].is_null() { self.int_property(&json["offset_y"], &join(path, "offset_y")); }
+            },
+            "move_player" => {
+                self.keys(json, path, &["type", "direction", "forced", "custom_distance"]);
+                if self.required(json, path, "direction") { self.direction(&json["direction"], &join(path, "direction")); }
+                if !json["forced"].is_null() { self.bool_property(&json["forced"], &join(path, "forced")); }
+                if !json["custom_distance"].is_null() { self.int_property(&json["custom_distance"], &join(path, "custom_distance")); }
+            },
+            "play_event" => {
+                self.keys(json, path, &["type", "event"]);
+                if self.required(json, path, "event") { self.string(&json["event"], &join(path, "event")); }
+            },
+            "random" => {
+                self.keys(json, path, &["type", "source", "mode", "action", "chance", "actions"]);
+                self.one_of(&json["source"], &join(path, "source"), &RANDOM_SOURCES);
+                self.one_of(&json["mode"], &join(path, "mode"), &["select", "chance"]);
+                if json["mode"].as_str() == Some("select") {
+                    if self.required(json, path, "actions") { self.action(&json["actions"], &join(path, "actions")); }
+                } else {
+                    if self.required(json, path, "action") { self.action(&json["action"], &join(path, "action")); }
+                    self.number(&json["chance"], &join(path, "chance"));
+                }
+            },
+            "set_layer_visible" => {
+                self.keys(json, path, &["type", "name", "visible"]);
+                if self.required(json, path, "name") { self.string(&json["name"], &join(path, "name")); }
+                if self.required(json, path, "visible") { self.boolean(&json["visible"], &join(path, "visible")); }
+            },
+            "lua" => {
+                self.keys(json, path, &["type", "function", "library", "args"]);
+                if self.required(json, path, "function") { self.string(&json["function"], &join(path, "function")); }
+                self.string(&json["library"], &join(path, "library"));
+                if !json["args"].is_null() && !json["args"].is_array() {
+                    self.wrong_type(&json["args"], &join(path, "args"), "an array");
+                }
+            },
+            other => self.report(&join(path, "type"), format!("unknown action type \"{}\"", other))
+        }
+    }
+
+    pub fn trigger(&mut self, json: &JsonValue, path: &str) {
+        if json.is_string() {
+            self.one_of(json, path, &TRIGGERS);
+        } else if json.is_array() {
+            for (i, trigger) in json.members().enumerate() {
+                self.trigger(trigger, &index(path, i));
+            }
+        } else if json.is_object() {
+            self.keys(json, path, &["type", "freq", "side"]);
+            if json["type"].as_str() == Some("tick") {
+                if self.required(json, path, "freq") {
+                    self.unsigned(&json["freq"], &join(path, "freq"));
+                }
+            } else if self.required(json, path, "type") {
+                self.trigger(&json["type"], &join(path, "type"));
+            }
+            self.direction(&json["side"], &join(path, "side"));
+        } else {
+            self.wrong_type(json, path, "a trigger string, array or object");
+        }
+    }
+
+    /// Checks an entity's `actions` property, a list of `{ "trigger": ..., "action": ... }`
+    pub fn triggered_actions(&mut self, json: &JsonValue, path: &str) {
+        if !json.is_array() {
+            self.wrong_type(json, path, "an array");
+            return;
+        }
+
+        for (i, entry) in json.members().enumerate() {
+            let entry_path = index(path, i);
+            if !entry.is_object() {
+                self.wrong_type(entry, &entry_path, "an object");
+                continue;
+            }
+            self.keys(entry, &entry_path, &["trigger", "action"]);
+            if self.required(entry, &entry_path, "trigger") { self.trigger(&entry["trigger"], &join(&entry_path, "trigger")); }
+            if self.required(entry, &entry_path, "action") { self.action(&entry["action"], &join(&entry_path, "action")); }
+        }
+    }
+
+    pub fn ai(&mut self, json: &JsonValue, path: &str) {
+        let tag = match self.tag(json, path) { Some(tag) => tag, None => return };
+        match tag {
+            "wander" => {
+                self.keys(json, path, &["type", "frequency", "delay", "speed", "move_delay"]);
+                self.number(&json["frequency"], &join(path, "frequency"));
+                self.number(&json["delay"], &join(path, "delay"));
+                self.unsigned(&json["speed"], &join(path, "speed"));
+                self.unsigned(&json["move_delay"], &join(path, "move_delay"));
+            },
+            "move_straight" => {
+                self.keys(json, path, &["type", "direction"]);
+                if self.required(json, path, "direction") { self.direction(&json["direction"], &join(path, "direction")); }
+            },
+            "chaser" => {
+                self.keys(json, path, &["type", "speed", "path_max", "detection_radius", "pathfinder"]);
+                self.unsigned(&json["speed"], &join(path, "speed"));
+                self.unsigned(&json["path_max"], &join(path, "path_max"));
+                self.unsigned(&json["detection_radius"], &join(path, "detection_radius"));
+                self.one_of(&json["pathfinder"], &join(path, "pathfinder"), &PATHFINDERS);
+            },
+            "push" | "bird" => {
+                self.keys(json, path, &["type", "speed"]);
+                self.unsigned(&json["speed"], &join(path, "speed"));
+            },
+            "animate_on_interact" => {
+                self.keys(json, path, &["type", "frames", "use", "bump", "walk", "side"]);
+                self.unsigned(&json["frames"], &join(path, "frames"));
+                for key in ["use", "bump", "walk"] {
+                    self.boolean(&json[key], &join(path, key));
+                }
+                self.direction(&json["side"], &join(path, "side"));
+            },
+            "script" => {
+                self.keys(json, path, &["type", "function", "speed", "move_delay"]);
+                if self.required(json, path, "function") { self.string(&json["function"], &join(path, "function")); }
+                self.unsigned(&json["speed"], &join(path, "speed"));
+                self.unsigned(&json["move_delay"], &join(path, "move_delay"));
+            },
+            other => self.report(&join(path, "type"), format!("unknown ai type \"{}\"", other))
+        }
+    }
+
+    /// A number, `"low,high"`, `[value]`, `[low, high]` or `{ "low": ..., "high": ... }`
+    fn particle_value(&mut self, json: &JsonValue, path: &str) {
+        match json {
+            JsonValue::Number(_) => (),
+            JsonValue::Short(_) | JsonValue::String(_) => {
+                if json.as_str().unwrap().split(',').any(|part| part.trim().parse::<f32>().is_err()) {
+                    self.report(path, "expected numbers separated by a comma".to_string());
+                }
+            },
+            JsonValue::Array(values) => {
+                if values.is_empty() || values.len() > 2 {
+                    self.report(path, format!("expected 1 or 2 numbers, found {}", values.len()));
+                }
+                for (i, value) in values.iter().enumerate() {
+                    self.number(value, &index(path, i));
+                }
+            },
+            JsonValue::Object(_) => {
+                self.keys(json, path, &["low", "high"]);
+                for key in ["low", "high"] {
+                    if self.required(json, path, key) { self.number(&json[key], &join(path, key)); }
+                }
+            },
+            _ => self.wrong_type(json, path, "a number, range string, array or object")
+        }
+    }
+
+    fn particle_pair(&mut self, json: &JsonValue, path: &str) {
+        if json.is_array() {
+            if json.len() != 2 {
+                self.report(path, format!("expected 2 values, found {}", json.len()));
+            }
+            for (i, value) in json.members().enumerate() {
+                self.particle_value(value, &index(path, i));
+            }
+        } else if json.is_object() {
+            self.keys(json, path, &["x", "y"]);
+            for key in ["x", "y"] {
+                if self.required(json, path, key) { self.particle_value(&json[key], &join(path, key)); }
+            }
+        } else {
+            self.wrong_type(json, path, "an array or object");
+        }
+    }
+
+    pub fn particles(&mut self, json: &JsonValue, path: &str) {
+        if !json.is_object() {
+            self.wrong_type(json, path, "an object");
+            return;
+        }
+
+        self.keys(json, path, &["lifetime", "pos_offset", "velocity", "acceleration", "tx_coord", "tx_vel", "freq", "texture", "size", "height", "freq_rand", "stagnate"]);
+        for key in ["lifetime", "stagnate"] {
+            if !json[key].is_null() { self.particle_value(&json[key], &join(path, key)); }
+        }
+        for key in ["pos_offset", "velocity", "acceleration", "tx_coord", "tx_vel"] {
+            if !json[key].is_null() { self.particle_pair(&json[key], &join(path, key)); }
+        }
+        self.unsigned(&json["freq"], &join(path, "freq"));
+        self.string(&json["texture"], &join(path, "texture"));
+        self.number(&json["height"], &join(path, "height"));
+        self.number(&json["freq_rand"], &join(path, "freq_rand"));
+        if !json["size"].is_null() {
+            let size = &json["size"];
+            let (x, y) = if size.is_array() { (&size[0], &size[1]) } else { (&size["x"], &size["y"]) };
+            if x.as_u32().is_none() || y.as_u32().is_none() {
+                self.report(&join(path, "size"), "expected two positive whole numbers".to_string());
+            }
+        }
+    }
+
+    /// Checks the json valued properties of one map object
+    pub fn object_properties(&mut self, properties: &HashMap<String, PropertyValue>) {
+        let checks: [(&str, fn(&mut Self, &JsonValue, &str)); 3] = [
+            ("actions", Self::triggered_actions),
+            ("ai", Self::ai),
+            ("particles", Self::particles)
+        ];
+
+        for (name, check) in checks {
+            if let Some(PropertyValue::StringValue(source)) = properties.get(name) {
+                match json::parse(source) {
+                    Ok(parsed) => check(self, &parsed, name),
+                    Err(e) => self.report(name, format!("invalid json: {}", e))
+                }
+            }
+        }
+    }
+}
+
+/// Lints every object in a map, returning problems prefixed with the object id
+pub fn lint_map(path: &Path) -> Result<Vec<String>, String> {
+    let map = Loader::new().load_tmx_map(path).map_err(|e| e.to_string())?;
+    let mut problems = Vec::new();
+
+    for layer in map.layers() {
+        if let LayerType::Objects(object_layer) = layer.layer_type() {
+            for object in object_layer.objects() {
+                let properties = match loader::merged_properties(&object.properties) {
+                    Ok(properties) => properties,
+                    Err(e) => {
+                        problems.push(format!("object {}: file: {}", object.id(), e));
+                        continue;
+                    }
+                };
+
+                let mut lint = Lint::new();
+                lint.object_properties(&properties);
+                problems.extend(lint.problems.into_iter().map(|problem| format!("object {}: {}", object.id(), problem)));
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Lints the given maps, or every map under `res/maps/`, returning how many problems were found
+pub fn lint_maps(maps: &[PathBuf]) -> Result<usize, String> {
+    let maps = if maps.is_empty() {
+        loader::map_files(Path::new(loader::MAPS_DIR)).map_err(|e| format!("could not read {}: {}", loader::MAPS_DIR, e))?
+    } else {
+        maps.to_vec()
+    };
+
+    let mut count = 0;
+    for map in maps.iter() {
+        match lint_map(map) {
+            Ok(problems) => {
+                for problem in problems.iter() {
+                    println!("{}: {}", map.display(), problem);
+                }
+                count += problems.len();
+            },
+            Err(e) => {
+                println!("{}: could not load map: {}", map.display(), e);
+                count += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
@@ -1,10 +1,12 @@
-use std::{any::Any, cell::RefCell, collections::HashMap, ffi::OsString, fs::{self, File}, io::Read, path::PathBuf, rc::Rc, u8};
+use std::{any::Any, cell::RefCell, collections::HashMap, ffi::OsString, fs, io, path::{Path, PathBuf}, rc::Rc, u8};
 
 use json::JsonValue;
 use sdl2::{render::{TextureCreator, TextureAccess}, pixels::{PixelFormatEnum, Color}, rect::Rect};
 use tiled::{Loader, Orientation, LayerType, TileLayer, PropertyValue, TilesetLocation};
 
-use crate::{actions::{self, MultipleAction}, ai::{self, parse_animator}, audio::Song, entity::{self, parse_trigger, Entity, TriggeredAction}, game::RenderState, particles, screen_event::ScreenEvent, texture::Texture, tiles::{SpecialTile, Tile, TileExits, Tilemap, Tileset}, world::{self, ImageLayer, Layer, World}};
+use crate::{actions::{self, MultipleAction}, ai::{self, parse_animator}, audio::Song, entity::{self, parse_trigger, Entity, TriggeredAction}, game::RenderState, lint::Lint, particles, screen_event::ScreenEvent, texture::Texture, tiles::{SpecialTile, Tile, TileExits, Tilemap, Tileset}, world::{self, ImageLayer, Layer, World}};
+
+pub const MAPS_DIR: &str = "res/maps/";
 
 impl<'a> World<'a> {
     pub fn load_from_file<T>(file: &String, creator: &'a TextureCreator<T>, old_world: &mut Option<World<'a>>, state: &RenderState) -> Result<World<'a>, Box<dyn std::error::Error>> {
@@ -151,6 +153,17 @@ impl<'a> World<'a> {
             }
         }
 
+        if let Some(prop) = map.properties.get("script") {
+            if let PropertyValue::StringValue(path) = prop {
+                let path = PathBuf::from("res/scripts/").join(path);
+                // Kept when missing so the script is loaded if it's created while the game runs
+                if !path.exists() {
+                    eprintln!("Script file \"{:?}\" not found", &path);
+                }
+                world.script = Some(path);
+            }
+        }
+
         if map.infinite() { return Err("infinite maps not supported".into()) }
         if !matches!(map.orientation, Orientation::Orthogonal) { return Err("non-orthogonal maps not supported".into()) }
         //assert!(!map.infinite(), "Infinite maps not supported");
@@ -332,15 +345,14 @@ impl<'a> World<'a> {
                                     script: None
                                 };
 
-                                let mut properties = object.properties.clone();
+                                let properties = merged_properties(&object.properties)?;
 
-                                let filename = if let Some(prop) = properties.get("file") { if let PropertyValue::StringValue(file) = prop { Some(file) } else { None } } else { None };
-
-                                if let Some(properties_filename) = filename {
-                                    let mut file = fs::File::open(properties_filename)?;
-                                    let mut source = String::new();
-                                    file.read_to_string(&mut source).unwrap();
-                                    json_to_properties(&mut properties, &mut json::parse(&source)?);
+                                if crate::DEBUG {
+                                    let mut lint = Lint::new();
+                                    lint.object_properties(&properties);
+                                    for problem in lint.problems.iter() {
+                                        eprintln!("Warning: {} object {}: {}", file, object.id(), problem);
+                                    }
                                 }
                                 
                                 if let Some(prop) = properties.get("height") { if let PropertyValue::IntValue(height) = prop { entity.height = *height; } }
@@ -433,14 +445,10 @@ impl<'a> World<'a> {
                                 if let Some(prop) = properties.get("script") {
                                     if let PropertyValue::StringValue(path) = prop {
                                         let path = PathBuf::from("res/scripts/").join(path);
-                                        let file = File::open(&path);
-                                        if let Ok(mut file) = file {
-                                            let mut source = String::new();
-                                            file.read_to_string(&mut source).expect("Failed to read script source");
-                                            entity.script = Some(source);
-                                        } else {
+                                        if !path.exists() {
                                             eprintln!("Script file \"{:?}\" not found", &path);
                                         }
+                                        entity.script = Some(path);
                                     }
                                 }
 
@@ -531,6 +539,34 @@ pub fn json_to_properties(properties: &mut HashMap<String, PropertyValue>, parse
     }
 }
 
+/// An object's properties with the properties from its `file` json merged in
+pub fn merged_properties(properties: &HashMap<String, PropertyValue>) -> Result<HashMap<String, PropertyValue>, String> {
+    let mut merged = properties.clone();
+    if let Some(PropertyValue::StringValue(file)) = properties.get("file") {
+        let source = fs::read_to_string(file).map_err(|e| format!("could not read {}: {}", file, e))?;
+        let mut parsed = json::parse(&source).map_err(|e| format!("could not parse {}: {}", file, e))?;
+        json_to_properties(&mut merged, &mut parsed);
+    }
+
+    Ok(merged)
+}
+
+/// Every `.tmx` map in `dir` and its subfolders, sorted
+pub fn map_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut maps = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            maps.extend(map_files(&path)?);
+        } else if path.extension().is_some_and(|extension| extension == "tmx") {
+            maps.push(path);
+        }
+    }
+    maps.sort();
+
+    Ok(maps)
+}
+
 pub fn property_to_json(property: &PropertyValue) -> JsonValue {
     match property {
         PropertyValue::BoolValue(b) => { return JsonValue::Boolean(*b) },
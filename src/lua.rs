@@ -1,126 +1,995 @@
-use std::collections::HashMap;
+use std::{cell::{Cell, RefCell}, collections::HashMap, fs, path::{Path, PathBuf}, rc::Rc, str::FromStr, time::SystemTime};
 
-use mlua::{Table, UserData};
+use json::JsonValue;
+use mlua::{HookTriggers, IntoLua, IntoLuaMulti, LuaOptions, StdLib, Table, UserData, Variadic, VmState};
 
-use crate::{entity::Entity, world::World};
+use crate::{ai::{AnimationFrameData, Animator}, audio::Song, controls::Action, entity::{Entity, VariableValue}, game::{Direction, Input, IntProperty, LevelPropertyType, QueuedLoad, WarpPos}, player::Player, save::{PersistData, PersistValue}, transitions::{Transition, TransitionType}, world::{Interaction, World}};
 
 const UPDATE_CALLBACK: &str = "_update";
 const ONLOAD_CALLBACK: &str = "_onload";
+const UNLOAD_CALLBACK: &str = "_onunload";
+const USE_CALLBACK: &str = "_on_use";
+const BUMP_CALLBACK: &str = "_on_bump";
+const WALK_CALLBACK: &str = "_on_walk";
+pub const EFFECT_SWITCH_CALLBACK: &str = "_on_effect_switch";
+
+/// Instructions a single callback may run before it is stopped
+const INSTRUCTION_BUDGET: u32 = 1_000_000;
+/// How often the budget hook runs, in instructions
+const BUDGET_HOOK_INTERVAL: u32 = 1000;
+/// Ticks between checks for changed scripts in debug builds
+const RELOAD_CHECK_INTERVAL: u32 = 30;
+/// Directory scanned for changed and new scripts
+const SCRIPTS_PATH: &str = "res/scripts/";
+/// Base library functions that can reach the filesystem
+const REMOVED_GLOBALS: [&str; 3] = ["dofile", "loadfile", "require"];
+
+/// Defines the functions cutscene coroutines yield through<br>
+/// `__cutscene_proxy` is passed to cutscenes as `world`, it forwards to whichever world is current when the cutscene resumes
+const CUTSCENE_PRELUDE: &str = r#"
+__cutscene_proxy = setmetatable({}, {
+    __index = function(_, key)
+        local value = __cutscene_world[key]
+        if type(value) == "function" then
+            return function(_, ...) return value(__cutscene_world, ...) end
+        end
+        return value
+    end
+})
+
+function wait(frames) coroutine.yield("wait", frames or 1) end
+function wait_for_confirm() coroutine.yield("confirm") end
+function walk_player(direction, tiles) coroutine.yield("walk_player", direction, tiles or 1) end
+function fade(speed, hold) coroutine.yield("fade", speed or 4, hold or 0) end
+"#;
+
+/// The entity callback that handles `interaction`
+pub fn interaction_callback(interaction: &Interaction) -> &'static str {
+    match interaction {
+        Interaction::Use(..) => USE_CALLBACK,
+        Interaction::Bump(..) => BUMP_CALLBACK,
+        Interaction::Walk(..) => WALK_CALLBACK
+    }
+}
+
+struct Script {
+    path: PathBuf,
+    env: Table
+}
+
+/// How deeply nested a persist table can be, also stops tables that contain themselves
+const MAX_PERSIST_DEPTH: u32 = 32;
+
+fn persist_value(value: &mlua::Value, depth: u32) -> Option<PersistValue> {
+    match value {
+        mlua::Value::Boolean(b) => Some(PersistValue::Bool(*b)),
+        mlua::Value::Integer(i) => Some(PersistValue::Int(*i as i64)),
+        mlua::Value::Number(n) => Some(PersistValue::Number(*n)),
+        mlua::Value::String(s) => Some(PersistValue::String(s.to_string_lossy())),
+        mlua::Value::Table(table) if depth < MAX_PERSIST_DEPTH => Some(PersistValue::Table(persist_entries(table, depth + 1))),
+        _ => None
+    }
+}
+
+/// Converts a lua table into saveable entries, skipping anything that can't be saved
+fn persist_entries(table: &Table, depth: u32) -> Vec<(PersistValue, PersistValue)> {
+    let mut entries = Vec::new();
+    for pair in table.pairs::<mlua::Value, mlua::Value>() {
+        let (key, value) = match pair {
+            Ok(pair) => pair,
+            Err(_) => continue
+        };
+
+        match (persist_value(&key, depth), persist_value(&value, depth)) {
+            (Some(key @ (PersistValue::String(_) | PersistValue::Int(_) | PersistValue::Number(_))), Some(value)) => entries.push((key, value)),
+            _ => eprintln!("Warning: persist tables can only hold numbers, strings, booleans and tables, skipped a {} = {}", key.type_name(), value.type_name())
+        }
+    }
+    entries
+}
+
+fn lua_value(lua: &mlua::Lua, value: &PersistValue) -> mlua::Result<mlua::Value> {
+    match value {
+        PersistValue::Bool(b) => Ok(mlua::Value::Boolean(*b)),
+        PersistValue::Int(i) => Ok(mlua::Value::Integer(*i)),
+        PersistValue::Number(n) => Ok(mlua::Value::Number(*n)),
+        PersistValue::String(s) => s.as_str().into_lua(lua),
+        PersistValue::Table(entries) => Ok(mlua::Value::Table(lua_table(lua, entries)?))
+    }
+}
+
+fn lua_table(lua: &mlua::Lua, entries: &Vec<(PersistValue, PersistValue)>) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    for (key, value) in entries.iter() {
+        table.raw_set(lua_value(lua, key)?, lua_value(lua, value)?)?;
+    }
+    Ok(table)
+}
+
+/// What a cutscene is waiting on before it is resumed
+enum CutsceneWait {
+    None,
+    Frames(u32),
+    Confirm,
+    /// Direction and tiles left to walk
+    WalkPlayer(Direction, u32),
+    Fade
+}
+
+/// A coroutine started by `cutscene(func, freeze)`, resumed every update until it finishes
+struct Cutscene {
+    thread: mlua::Thread,
+    wait: CutsceneWait,
+    /// Keeps the player frozen while the cutscene runs, defaults to true
+    freeze: bool,
+    /// The script that started the cutscene, for error reports
+    path: PathBuf,
+    entity_id: Option<u32>
+}
+
+fn number_arg(value: Option<mlua::Value>) -> Option<i64> {
+    match value {
+        Some(mlua::Value::Integer(i)) => Some(i as i64),
+        Some(mlua::Value::Number(n)) => Some(n as i64),
+        _ => None
+    }
+}
+
+/// Reads the values a cutscene yielded, starting a fade if it asked for one
+fn parse_wait(values: mlua::MultiValue, world: &mut World) -> Result<CutsceneWait, String> {
+    let mut values = values.into_iter();
+    let kind = match values.next() {
+        Some(mlua::Value::String(kind)) => kind.to_string_lossy(),
+        // A plain coroutine.yield() waits a frame
+        None => return Ok(CutsceneWait::Frames(1)),
+        Some(other) => return Err(format!("cutscene yielded a {} instead of a wait", other.type_name()))
+    };
+
+    match kind.as_str() {
+        "wait" => Ok(CutsceneWait::Frames(number_arg(values.next()).unwrap_or(1).max(0) as u32)),
+        "confirm" => Ok(CutsceneWait::Confirm),
+        "walk_player" => {
+            let direction = match values.next() {
+                Some(mlua::Value::String(direction)) => Direction::from_str(&direction.to_string_lossy()).map_err(|_| format!("invalid direction \"{}\"", direction.to_string_lossy()))?,
+                _ => return Err("walk_player needs a direction".to_string())
+            };
+            Ok(CutsceneWait::WalkPlayer(direction, number_arg(values.next()).unwrap_or(1).max(0) as u32))
+        },
+        "fade" => {
+            let speed = number_arg(values.next()).unwrap_or(4) as i32;
+            let hold = number_arg(values.next()).unwrap_or(0).max(0) as u32;
+            world.transition = Some(Transition::new(TransitionType::Fade, speed, 0, false, hold, false));
+            Ok(CutsceneWait::Fade)
+        },
+        _ => Err(format!("unknown cutscene wait \"{}\"", kind))
+    }
+}
+
+/// An argument passed to a lua function by a `lua` action
+#[derive(Clone)]
+pub enum ScriptArg {
+    Int(i32),
+    String(String)
+}
+
+impl IntoLua for ScriptArg {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        match self {
+            Self::Int(i) => i.into_lua(lua),
+            Self::String(s) => s.into_lua(lua)
+        }
+    }
+}
+
+/// A call to a lua function queued by a `lua` action, run at the end of the world update
+pub struct ScriptCall {
+    /// Index of the entity that ran the action, its script is called if it has one
+    pub entity: Option<usize>,
+    /// Library script to call the function in instead of the entity or map script
+    pub library: Option<PathBuf>,
+    pub function: String,
+    pub args: Vec<ScriptArg>
+}
+
+fn has_function(env: &Table, name: &str) -> bool {
+    matches!(env.get::<mlua::Value>(name), Ok(mlua::Value::Function(_)))
+}
+
+/// Records the modification time of every `.lua` file in `dir` and its subdirectories
+fn scan_scripts(dir: &Path, times: &mut HashMap<PathBuf, SystemTime>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            scan_scripts(&path, times);
+        } else if path.extension().is_some_and(|extension| extension == "lua") {
+            if let Ok(modified) = entry.metadata().and_then(|metadata| metadata.modified()) {
+                times.insert(path, modified);
+            }
+        }
+    }
+}
 
 pub struct ScriptingContext {
     lua: mlua::Lua,
-    entity_scripts: HashMap<u32, Table>,
-    world_script: Option<Table>
+    entity_scripts: HashMap<u32, Script>,
+    world_script: Option<Script>,
+    /// Shared scripts called by `lua` actions, loaded on first use<br>
+    /// `None` if the library failed to load
+    libraries: HashMap<PathBuf, Option<Table>>,
+    /// Running cutscenes, shared with the lua `cutscene` function
+    cutscenes: Rc<RefCell<Vec<Cutscene>>>,
+    /// Instructions the current call has left, counted down by the budget hook
+    budget: Rc<Cell<u32>>,
+    /// Each script's `persist` table, which outlives the script's environment
+    persist: HashMap<PathBuf, Table>,
+    /// Modification time of every file in `res/scripts/` at the last check, for hot reloading<br>
+    /// `None` until the first check
+    modified_times: Option<HashMap<PathBuf, SystemTime>>,
+    reload_timer: u32,
+    /// Script errors on the current map, shown by the debug overlay
+    pub errors: Vec<String>
 }
 
 impl ScriptingContext {
-    pub fn add_entity_script(&mut self, id: u32, source: &str) {
-        // TODO: Proper error handling on invalid script
+    pub fn add_entity_script(&mut self, id: u32, path: &PathBuf) {
+        match self.load_script(path, Some(id)) {
+            Ok(env) => {
+                self.entity_scripts.insert(id, Script { path: path.clone(), env });
+            },
+            Err(e) => self.report_error(path, Some(id), &e)
+        }
+    }
+
+    /// Removes every entity script and clears errors, called before a map's scripts are added
+    pub fn clear_entity_scripts(&mut self) {
+        self.entity_scripts.clear();
+        self.errors.clear();
+    }
+
+    /// Loads the map's script, replacing the previous map's
+    pub fn set_world_script(&mut self, path: Option<&PathBuf>) {
+        self.world_script = None;
+        if let Some(path) = path {
+            match self.load_script(path, None) {
+                Ok(env) => self.world_script = Some(Script { path: path.clone(), env }),
+                Err(e) => self.report_error(path, None, &e)
+            }
+        }
+    }
 
-        let chunk = self.lua.load(source);
+    /// Compiles and runs a script in its own environment, returning the environment to look callbacks up in
+    fn load_script(&mut self, path: &PathBuf, entity_id: Option<u32>) -> mlua::Result<Table> {
         // Create an enclosing table to separate each script
-        let script_env = self.lua.create_table().unwrap(); 
+        let script_env = self.lua.create_table()?;
 
         // Have global function calls fallback to the default globals so user can use print, math, etc.
         let globals = self.lua.globals();
-        let meta = self.lua.create_table().unwrap();
-        meta.set("__index", globals).unwrap();
-        script_env.set_metatable(Some(meta)).unwrap();
+        let meta = self.lua.create_table()?;
+        meta.set("__index", globals)?;
+        script_env.set_metatable(Some(meta))?;
+        script_env.set("persist", self.persist_table(path)?)?;
+        script_env.set("cutscene", self.cutscene_function(path, entity_id)?)?;
 
-        let script_func = chunk.set_environment(script_env.clone()).into_function().unwrap();
-        // Run the script to initialize callbacks
-        script_func.call::<()>(()).unwrap(); 
-        self.entity_scripts.insert(id, script_env);
+        self.run_script(&script_env, path)?;
+        Ok(script_env)
     }
 
-    pub fn on_update(&mut self, world: &mut World) {
-        self.lua.scope(|scope| {
-            let entities_size = world.entities.as_ref().unwrap().len();
-            let entity_ids: Vec<u32> = world.entities.as_ref().unwrap().iter().map(|e| e.id).collect();
-
-            let world_wrapper = WorldWrapper { world };
-            let lua_world_userdata = scope.create_userdata(world_wrapper).unwrap();
-            // let mut placeholder = Some(Entity::new());
-            for i in 0..entities_size {
-                let id: u32 = entity_ids[i];
-                let script_env = self.entity_scripts.get(&id);
-
-                if let Some(script_env) = script_env {
-                    if let Ok(func) = script_env.get::<mlua::Function>(UPDATE_CALLBACK) {
-                        // If the entity has a script and a valid update function
-                        // let mut entity = std::mem::replace(world_wrapper.world.entities.as_mut().unwrap().get_mut(i).unwrap(), placeholder.take().unwrap());
-                        // let entity_ref = world.entities.as_mut().unwrap().get_mut(i).unwrap();
-                        // let entity_wrapper = EntityWrapper { entity: entity_ref };
-                        // let lua_entity_userdata = scope.create_userdata(entity_wrapper).unwrap();
-
-                        // TODO: proper runtime error handling
-                        func.call::<()>((&lua_world_userdata, id)).unwrap();
-
-                        // placeholder = Some(std::mem::replace(world_wrapper.world.entities.as_mut().unwrap().get_mut(i).unwrap(), entity));
-                    }
-                }  
+    /// Runs a script's source in `env`, which defines its callbacks there
+    fn run_script(&mut self, env: &Table, path: &PathBuf) -> mlua::Result<()> {
+        let source = fs::read_to_string(path).map_err(|e| mlua::Error::RuntimeError(format!("could not read script: {}", e)))?;
+        // The @ prefix makes lua report the name as a file path in errors and tracebacks
+        let chunk = self.lua.load(&source).set_name(format!("@{}", path.display()));
+        let script_func = chunk.set_environment(env.clone()).into_function()?;
+        self.reset_budget();
+        script_func.call::<()>(())
+    }
+
+    /// Scans `res/scripts/` and re-runs scripts whose files changed, swapping their new functions into the existing environments<br>
+    /// Variables the old code set are kept, only the functions are replaced<br>
+    /// Scripts the map asks for that aren't loaded, because they were missing, failed or errored, are loaded and get `_onload`
+    fn reload_changed_scripts(&mut self, world: &mut World, player: &mut Player) {
+        let mut times = HashMap::new();
+        scan_scripts(Path::new(SCRIPTS_PATH), &mut times);
+        // The first scan only records the times, everything was just loaded
+        let previous = match self.modified_times.replace(times.clone()) {
+            Some(previous) => previous,
+            None => return
+        };
+
+        let mut changed = times.into_iter()
+            .filter(|(path, time)| previous.get(path) != Some(time))
+            .map(|(path, _)| path)
+            .collect::<Vec<PathBuf>>();
+        changed.sort();
+
+        for path in changed.into_iter() {
+            if previous.contains_key(&path) {
+                println!("Reloading script {}", path.display());
+            } else {
+                println!("Found new script {}", path.display());
             }
 
+            let mut envs = Vec::new();
+            for (id, script) in self.entity_scripts.iter() {
+                if script.path == path {
+                    envs.push((Some(*id), script.env.clone()));
+                }
+            }
+            if let Some(script) = self.world_script.as_ref().filter(|script| script.path == path) {
+                envs.push((None, script.env.clone()));
+            }
+            match self.libraries.get(&path) {
+                Some(Some(env)) => envs.push((None, env.clone())),
+                // Loaded again on its next use
+                Some(None) => { self.libraries.remove(&path); },
+                None => ()
+            }
+
+            for (id, env) in envs.into_iter() {
+                if let Err(e) = self.reload_script(&env, &path) {
+                    self.report_error(&path, id, &e);
+                }
+            }
+
+            self.load_missing_scripts(&path, world, player);
+        }
+    }
+
+    /// Loads the map's and entities' scripts at `path` that aren't running and calls their `_onload`
+    fn load_missing_scripts(&mut self, path: &PathBuf, world: &mut World, player: &mut Player) {
+        if self.world_script.is_none() && world.script.as_ref() == Some(path) {
+            self.set_world_script(Some(path));
+            self.call_world_callback(ONLOAD_CALLBACK, world, player, ());
+        }
+
+        for i in 0..world.entities.as_ref().unwrap().len() {
+            let entity = &world.entities.as_ref().unwrap()[i];
+            if entity.script.as_ref() != Some(path) || self.entity_scripts.contains_key(&entity.id) {
+                continue;
+            }
+
+            let id = entity.id;
+            self.add_entity_script(id, path);
+            let mut entity = std::mem::replace(world.entities.as_mut().unwrap().get_mut(i).unwrap(), Entity::new());
+            self.call_entity_callback(ONLOAD_CALLBACK, &mut entity, world, player, ());
+            *world.entities.as_mut().unwrap().get_mut(i).unwrap() = entity;
+        }
+    }
+
+    /// Runs the new source of a script in a scratch table so its top level assignments can't reset live state,
+    /// then copies its functions and any variables `env` doesn't have yet into `env`
+    fn reload_script(&mut self, env: &Table, path: &PathBuf) -> mlua::Result<()> {
+        let scratch = self.lua.create_table()?;
+        let meta = self.lua.create_table()?;
+        meta.set("__index", env.clone())?;
+        scratch.set_metatable(Some(meta.clone()))?;
+        self.run_script(&scratch, path)?;
+
+        for pair in scratch.pairs::<mlua::Value, mlua::Value>() {
+            let (key, value) = pair?;
+            if value.is_function() || env.raw_get::<mlua::Value>(key.clone())?.is_nil() {
+                env.raw_set(key, value)?;
+            }
+        }
+
+        // The new functions keep the scratch table as their globals, so it's emptied and forwards everything to `env`
+        scratch.clear()?;
+        meta.set("__newindex", env.clone())?;
+        Ok(())
+    }
+
+    /// The lua `cutscene(func, freeze)` function for one script, which remembers the script so errors can name it
+    fn cutscene_function(&self, path: &Path, entity_id: Option<u32>) -> mlua::Result<mlua::Function> {
+        let started = self.cutscenes.clone();
+        let path = path.to_path_buf();
+        self.lua.create_function(move |lua, (func, freeze): (mlua::Function, Option<bool>)| {
+            started.borrow_mut().push(Cutscene {
+                thread: lua.create_thread(func)?,
+                wait: CutsceneWait::None,
+                freeze: freeze.unwrap_or(true),
+                path: path.clone(),
+                entity_id
+            });
             Ok(())
-        }).unwrap();
+        })
+    }
+
+    /// The `persist` table for the script at `path`, shared by every entity using that script
+    fn persist_table(&mut self, path: &PathBuf) -> mlua::Result<Table> {
+        if let Some(table) = self.persist.get(path) {
+            return Ok(table.clone());
+        }
+
+        let table = self.lua.create_table()?;
+        self.persist.insert(path.clone(), table.clone());
+        Ok(table)
+    }
+
+    /// Copies every persist table out of lua for saving
+    pub fn persist_data(&self) -> PersistData {
+        let global = self.lua.globals().get::<Table>("global_persist").map(|table| persist_entries(&table, 0)).unwrap_or_default();
+        let scripts = self.persist.iter()
+            .map(|(path, table)| (path.to_string_lossy().to_string(), persist_entries(table, 0)))
+            .collect();
+
+        PersistData { global, scripts }
+    }
+
+    /// Replaces every persist table with the ones from a save, or empty ones for a new game
+    pub fn load_persist(&mut self, data: &PersistData) {
+        self.persist.clear();
+        for (path, entries) in data.scripts.iter() {
+            match lua_table(&self.lua, entries) {
+                Ok(table) => { self.persist.insert(PathBuf::from(path), table); },
+                Err(e) => eprintln!("Warning: failed to load persist table for {}: {}", path, e)
+            }
+        }
+
+        let global = lua_table(&self.lua, &data.global).unwrap_or_else(|e| {
+            eprintln!("Warning: failed to load global persist table: {}", e);
+            self.lua.create_table().unwrap()
+        });
+        self.lua.globals().set("global_persist", global).unwrap();
+
+        // Scripts that are already running have to see the new tables too
+        let mut envs = Vec::new();
+        for script in self.entity_scripts.values().chain(self.world_script.iter()) {
+            envs.push((script.path.clone(), script.env.clone()));
+        }
+        for (path, env) in self.libraries.iter() {
+            if let Some(env) = env {
+                envs.push((path.clone(), env.clone()));
+            }
+        }
+        for (path, env) in envs.into_iter() {
+            let table = self.persist_table(&path).unwrap();
+            env.set("persist", table).unwrap();
+        }
+    }
+
+    pub fn reset_persist(&mut self) {
+        self.load_persist(&PersistData::default());
+    }
+
+    /// Gives the next call a full instruction budget, call this before running any lua
+    fn reset_budget(&self) {
+        self.budget.set(INSTRUCTION_BUDGET);
+    }
+
+    fn report_error(&mut self, path: &Path, entity_id: Option<u32>, error: &mlua::Error) {
+        let message = match entity_id {
+            Some(id) => format!("Script error in {} (entity {}): {}", path.display(), id, error),
+            None => format!("Script error in {}: {}", path.display(), error)
+        };
+        eprintln!("{}", message);
+        self.errors.push(message);
+    }
+
+    pub fn on_update(&mut self, world: &mut World, player: &mut Player, input: &Input) {
+        if cfg!(debug_assertions) {
+            self.reload_timer += 1;
+            if self.reload_timer >= RELOAD_CHECK_INTERVAL {
+                self.reload_timer = 0;
+                self.reload_changed_scripts(world, player);
+            }
+        }
+
+        self.call_world_callback(UPDATE_CALLBACK, world, player, ());
+        self.call_all_entities(UPDATE_CALLBACK, world, player);
+        self.update_cutscenes(world, player, input);
+    }
+
+    fn update_cutscenes(&mut self, world: &mut World, player: &mut Player, input: &Input) {
+        // Taken out so cutscenes can start other cutscenes while they run
+        let mut cutscenes = std::mem::take(&mut *self.cutscenes.borrow_mut());
+        cutscenes.retain_mut(|cutscene| self.update_cutscene(cutscene, world, player, input));
+        cutscenes.append(&mut self.cutscenes.borrow_mut());
+        *self.cutscenes.borrow_mut() = cutscenes;
+    }
+
+    /// Resumes `cutscene` if what it waits on is done, returns false once it has finished
+    fn update_cutscene(&mut self, cutscene: &mut Cutscene, world: &mut World, player: &mut Player, input: &Input) -> bool {
+        if cutscene.freeze {
+            // The player can't move while frozen, so walk_player unfreezes them and blocks input instead
+            let walking = matches!(cutscene.wait, CutsceneWait::WalkPlayer(..));
+            player.frozen = !walking;
+            player.disable_player_input = walking;
+        }
+
+        let ready = match &mut cutscene.wait {
+            CutsceneWait::None => true,
+            CutsceneWait::Frames(frames) => {
+                *frames = frames.saturating_sub(1);
+                *frames == 0
+            },
+            CutsceneWait::Confirm => input.get_action_just_pressed(Action::Confirm),
+            CutsceneWait::WalkPlayer(direction, tiles) => {
+                if player.moving {
+                    false
+                } else if *tiles > 0 && player.can_move_in_direction(*direction, world) {
+                    player.force_move_player(*direction, world);
+                    *tiles -= 1;
+                    false
+                } else {
+                    true
+                }
+            },
+            CutsceneWait::Fade => world.transition.is_none()
+        };
+
+        if !ready {
+            return true;
+        }
+
+        let proxy = self.lua.globals().get::<Table>("__cutscene_proxy").unwrap();
+        self.reset_budget();
+        let result = self.lua.scope(|scope| {
+            let lua_world_userdata = scope.create_userdata(WorldWrapper { world: &mut *world, player: &mut *player })?;
+            self.lua.globals().set("__cutscene_world", lua_world_userdata)?;
+            cutscene.thread.resume::<mlua::MultiValue>(proxy)
+        });
+        self.lua.globals().set("__cutscene_world", mlua::Value::Nil).unwrap();
+
+        match result {
+            Ok(values) if cutscene.thread.status() == mlua::ThreadStatus::Resumable => {
+                match parse_wait(values, world) {
+                    Ok(wait) => {
+                        cutscene.wait = wait;
+                        return true;
+                    },
+                    Err(e) => self.report_error(&cutscene.path, cutscene.entity_id, &mlua::Error::RuntimeError(e))
+                }
+            },
+            Ok(_) => (),
+            Err(e) => self.report_error(&cutscene.path, cutscene.entity_id, &e)
+        }
+
+        if cutscene.freeze {
+            player.frozen = false;
+            player.disable_player_input = false;
+        }
+        false
+    }
+
+    pub fn on_load(&mut self, world: &mut World, player: &mut Player) {
+        self.call_world_callback(ONLOAD_CALLBACK, world, player, ());
+        self.call_all_entities(ONLOAD_CALLBACK, world, player);
+    }
+
+    /// Called before the current map is replaced
+    pub fn on_unload(&mut self, world: &mut World, player: &mut Player) {
+        self.call_world_callback(UNLOAD_CALLBACK, world, player, ());
+    }
+
+    /// Calls `callback(world, ...)` in the map's script if it defines one<br>
+    /// A map script that errors is disabled until the next map load
+    fn call_world_callback<A: IntoLuaMulti>(&mut self, callback: &str, world: &mut World, player: &mut Player, args: A) {
+        let func = match self.world_script.as_ref().map(|script| script.env.get::<mlua::Function>(callback)) {
+            Some(Ok(func)) => func,
+            _ => return
+        };
+
+        let result = self.call_with_world(&func, world, player, args);
+
+        if let Err(e) = result {
+            if let Some(script) = self.world_script.take() {
+                self.report_error(&script.path, None, &e);
+            }
+        }
     }
 
-    pub fn on_load(&mut self, world: &mut World) {
+    fn call_with_world<A: IntoLuaMulti>(&self, func: &mlua::Function, world: &mut World, player: &mut Player, args: A) -> mlua::Result<()> {
+        self.reset_budget();
         self.lua.scope(|scope| {
-            let entities_size = world.entities.as_ref().unwrap().len();
-            let entity_ids: Vec<u32> = world.entities.as_ref().unwrap().iter().map(|e| e.id).collect();
-
-            let world_wrapper = WorldWrapper { world };
-            let lua_world_userdata = scope.create_userdata(world_wrapper).unwrap();
-            for i in 0..entities_size {
-                let id: u32 = entity_ids[i];
-                let script_env = self.entity_scripts.get(&id);
-
-                if let Some(script_env) = script_env {
-                    if let Ok(func) = script_env.get::<mlua::Function>(ONLOAD_CALLBACK) {
-                        // TODO: proper runtime error handling
-                        func.call::<()>((&lua_world_userdata, id)).unwrap();
+            let lua_world_userdata = scope.create_userdata(WorldWrapper { world: &mut *world, player: &mut *player })?;
+            let mut call_args = args.into_lua_multi(&self.lua)?;
+            call_args.push_front(mlua::Value::UserData(lua_world_userdata));
+            func.call::<()>(call_args)
+        })
+    }
+
+    /// Loads a library script the first time it is used
+    fn library(&mut self, path: &PathBuf) -> Option<Table> {
+        if !self.libraries.contains_key(path) {
+            let env = match self.load_script(path, None) {
+                Ok(env) => Some(env),
+                Err(e) => {
+                    self.report_error(path, None, &e);
+                    None
+                }
+            };
+            self.libraries.insert(path.clone(), env);
+        }
+
+        self.libraries.get(path).unwrap().clone()
+    }
+
+    /// Runs the calls queued by `lua` actions this update<br>
+    /// Functions are looked up in the library if one is given, then the calling entity's script, then the map's script
+    pub fn run_script_calls(&mut self, world: &mut World, player: &mut Player) {
+        for call in std::mem::take(&mut world.special_context.script_calls).into_iter() {
+            let args = Variadic::from_iter(call.args);
+
+            if let Some(library) = &call.library {
+                if let Some(env) = self.library(library) {
+                    if !has_function(&env, &call.function) {
+                        eprintln!("Warning: function \"{}\" not found in {}", call.function, library.display());
+                        continue;
+                    }
+                    let func = env.get::<mlua::Function>(call.function.as_str()).unwrap();
+                    if let Err(e) = self.call_with_world(&func, world, player, args) {
+                        self.report_error(library, None, &e);
                     }
-                }  
+                }
+                continue;
             }
 
-            Ok(())
-        }).unwrap();
+            let entity_script = call.entity.and_then(|i| {
+                let id = world.entities.as_ref().unwrap().get(i)?.id;
+                self.entity_scripts.get(&id).filter(|script| has_function(&script.env, &call.function)).map(|_| i)
+            });
+
+            if let Some(i) = entity_script {
+                let mut entity = std::mem::replace(world.entities.as_mut().unwrap().get_mut(i).unwrap(), Entity::new());
+                self.call_entity_callback(&call.function, &mut entity, world, player, args);
+                *world.entities.as_mut().unwrap().get_mut(i).unwrap() = entity;
+            } else if self.world_script.as_ref().is_some_and(|script| has_function(&script.env, &call.function)) {
+                self.call_world_callback(&call.function, world, player, args);
+            } else {
+                eprintln!("Warning: lua action function \"{}\" not found", call.function);
+            }
+        }
+    }
+
+    /// Runs `callback` for every scripted entity, swapping each one out of the entity list like `World::update` does
+    fn call_all_entities(&mut self, callback: &str, world: &mut World, player: &mut Player) {
+        let mut placeholder = Some(Entity::new());
+        for i in 0..world.entities.as_ref().unwrap().len() {
+            let id = world.entities.as_ref().unwrap()[i].id;
+            if !self.entity_scripts.contains_key(&id) {
+                continue;
+            }
+
+            let mut entity = std::mem::replace(world.entities.as_mut().unwrap().get_mut(i).unwrap(), placeholder.take().unwrap());
+            self.call_entity_callback(callback, &mut entity, world, player, ());
+            placeholder = Some(std::mem::replace(world.entities.as_mut().unwrap().get_mut(i).unwrap(), entity));
+        }
+    }
+
+    /// Calls `callback(world, self, ...)` in the entity's script if it defines one<br>
+    /// `entity` must not be in `world.entities` while this runs, swap it out with a placeholder first<br>
+    /// A script that errors is disabled until the next map load
+    pub fn call_entity_callback<A: IntoLuaMulti>(&mut self, callback: &str, entity: &mut Entity, world: &mut World, player: &mut Player, args: A) {
+        let func = match self.entity_scripts.get(&entity.id).map(|script| script.env.get::<mlua::Function>(callback)) {
+            Some(Ok(func)) => func,
+            _ => return
+        };
+
+        let mut walk = None;
+        self.reset_budget();
+        let result = self.lua.scope(|scope| {
+            let lua_world_userdata = scope.create_userdata(WorldWrapper { world: &mut *world, player: &mut *player })?;
+            let lua_entity_userdata = scope.create_userdata(EntityWrapper { entity: &mut *entity, walk: &mut walk })?;
+
+            let mut call_args = args.into_lua_multi(&self.lua)?;
+            call_args.push_front(mlua::Value::UserData(lua_entity_userdata));
+            call_args.push_front(mlua::Value::UserData(lua_world_userdata));
+            func.call::<()>(call_args)
+        });
+
+        self.finish_entity_call(result, walk, entity, world, player);
+    }
+
+    /// Calls the `script` ai's tick function as `function(ai, self)`
+    fn call_ai_function(&mut self, function: &str, entity: &mut Entity, world: &mut World, player: &mut Player) {
+        let func = match self.entity_scripts.get(&entity.id).map(|script| script.env.get::<mlua::Function>(function)) {
+            Some(Ok(func)) => func,
+            Some(Err(_)) => {
+                eprintln!("Warning: ai function \"{}\" not found for entity {}", function, entity.id);
+                return;
+            },
+            None => return
+        };
+
+        let mut walk = None;
+        self.reset_budget();
+        let result = self.lua.scope(|scope| {
+            let lua_ai_userdata = scope.create_userdata(AiWrapper { world: &*world, player: &*player, height: entity.height })?;
+            let lua_entity_userdata = scope.create_userdata(EntityWrapper { entity: &mut *entity, walk: &mut walk })?;
+            func.call::<()>((lua_ai_userdata, lua_entity_userdata))
+        });
+
+        self.finish_entity_call(result, walk, entity, world, player);
+    }
+
+    /// Reports an entity script error or runs the walk the script asked for
+    fn finish_entity_call(&mut self, result: mlua::Result<()>, walk: Option<Direction>, entity: &mut Entity, world: &mut World, player: &mut Player) {
+        if let Err(e) = result {
+            if let Some(script) = self.entity_scripts.remove(&entity.id) {
+                self.report_error(&script.path, Some(entity.id), &e);
+            }
+            return;
+        }
+
+        // Walking needs the world and player, which the world userdata holds during the call
+        if let Some(direction) = walk {
+            let no_entities = Vec::new();
+            let entity_list = world.entities.as_ref().unwrap_or(&no_entities);
+            entity.walk(direction, world, player, entity_list);
+        }
+    }
+
+    /// Runs the ticks queued by `script` ais this update
+    pub fn run_ai_scripts(&mut self, world: &mut World, player: &mut Player) {
+        for (id, function) in std::mem::take(&mut world.special_context.script_ai_ticks).into_iter() {
+            let index = match world.entities.as_ref().unwrap().iter().position(|entity| entity.id == id) {
+                Some(index) => index,
+                None => continue
+            };
+
+            let mut entity = std::mem::replace(world.entities.as_mut().unwrap().get_mut(index).unwrap(), Entity::new());
+            self.call_ai_function(&function, &mut entity, world, player);
+            *world.entities.as_mut().unwrap().get_mut(index).unwrap() = entity;
+        }
     }
 
     pub fn new() -> Self {
+        // Scripts only get the standard libraries that can't touch the system, `os`, `io`, `package` and `debug` are left out
+        let lua = mlua::Lua::new_with(StdLib::COROUTINE | StdLib::TABLE | StdLib::STRING | StdLib::UTF8 | StdLib::MATH, LuaOptions::default()).unwrap();
+        for name in REMOVED_GLOBALS {
+            lua.globals().set(name, mlua::Value::Nil).unwrap();
+        }
+
+        let budget = Rc::new(Cell::new(INSTRUCTION_BUDGET));
+        let remaining = budget.clone();
+        lua.set_hook(HookTriggers::new().every_nth_instruction(BUDGET_HOOK_INTERVAL), move |_, _| {
+            if remaining.get() <= BUDGET_HOOK_INTERVAL {
+                return Err(mlua::Error::RuntimeError(format!("script ran over its budget of {} instructions", INSTRUCTION_BUDGET)));
+            }
+            remaining.set(remaining.get() - BUDGET_HOOK_INTERVAL);
+            Ok(VmState::Continue)
+        }).unwrap();
+
+        let cutscenes = Rc::new(RefCell::new(Vec::new()));
+        lua.load(CUTSCENE_PRELUDE).set_name("@cutscene").exec().unwrap();
+        lua.globals().set("global_persist", lua.create_table().unwrap()).unwrap();
+
         Self {
-            lua: mlua::Lua::new(),
+            lua,
             entity_scripts: HashMap::new(),
-            world_script: None
+            world_script: None,
+            libraries: HashMap::new(),
+            cutscenes,
+            budget,
+            persist: HashMap::new(),
+            modified_times: None,
+            reload_timer: 0,
+            errors: Vec::new()
         }
     }
 }
 
-struct WorldWrapper<'a, 'w> {
-    world: &'a mut World<'w>
+struct WorldWrapper<'a, 'w, 'p> {
+    world: &'a mut World<'w>,
+    player: &'a mut Player<'p>
+}
+
+/// Read only view of the world passed to `script` ais
+struct AiWrapper<'a, 'w, 'p> {
+    world: &'a World<'w>,
+    player: &'a Player<'p>,
+    /// Layer the entity collides on
+    height: i32
+}
+
+impl UserData for AiWrapper<'_, '_, '_> {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        // Outside the map counts as a collision
+        methods.add_method("get_collision", |_, this, (x, y): (i32, i32)| {
+            if x < 0 || y < 0 || x >= this.world.width as i32 || y >= this.world.height as i32 {
+                return Ok(true);
+            }
+            Ok(this.world.get_collision_at_tile(x as u32, y as u32, this.height))
+        });
+
+        methods.add_method("get_player_pos", |_, this, ()| {
+            Ok((this.player.x / 16, this.player.y / 16))
+        });
+    }
 }
 
-// Update this is never yused beasdcue its abd and abd anmd bad
-// /// This is only ever used for scripted entities on themselves
-// struct EntityWrapper<'a> {
-//     entity: &'a mut Entity
-// }
+/// Handle a scripted entity gets to itself, only valid during the callback it was passed to
+struct EntityWrapper<'a> {
+    entity: &'a mut Entity,
+    /// Walk requested by the script, started once the callback returns
+    walk: &'a mut Option<Direction>
+}
 
-impl UserData for WorldWrapper<'_, '_> {
+impl UserData for WorldWrapper<'_, '_, '_> {
     fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
-        methods.add_method_mut("test", |_, this, ()| {
-            this.world.snow.enabled = true;
+        // Flags, unset flags read as 0 like in json conditions
+        methods.add_method("get_flag", |_, this, name: String| {
+            Ok(*this.world.flags.get(&name).unwrap_or(&0))
+        });
+
+        methods.add_method_mut("set_flag", |_, this, (name, value): (String, i32)| {
+            this.world.flags.insert(name, value);
+            Ok(())
+        });
+
+        methods.add_method("get_global_flag", |_, this, name: String| {
+            Ok(*this.world.global_flags.get(&name).unwrap_or(&0))
+        });
+
+        methods.add_method_mut("set_global_flag", |_, this, (name, value): (String, i32)| {
+            this.world.global_flags.insert(name, value);
+            Ok(())
+        });
+
+        // Player, positions are in tiles
+        methods.add_method("get_player_pos", |_, this, ()| {
+            Ok((this.player.x / 16, this.player.y / 16))
+        });
+
+        methods.add_method("get_player_effect", |_, this, ()| {
+            Ok(this.player.current_effect.as_ref().map(|effect| effect.parsable().to_string()))
+        });
+
+        // Warps to `map` in res/maps/, to the map's default position if `x` and `y` are nil
+        methods.add_method_mut("warp", |_, this, (map, x, y, transition): (String, Option<i32>, Option<i32>, Option<String>)| {
+            let pos = WarpPos {
+                x: x.map_or(IntProperty::Level(LevelPropertyType::DefaultX), |x| IntProperty::Int(x)),
+                y: y.map_or(IntProperty::Level(LevelPropertyType::DefaultY), |y| IntProperty::Int(y))
+            };
+            let transition = match transition {
+                Some(name) => Some(Transition::parse(&JsonValue::from(name.as_str())).ok_or(mlua::Error::RuntimeError(format!("unknown transition \"{}\"", name)))?),
+                None => Transition::parse(&JsonValue::from("fade"))
+            };
+
+            this.world.queued_load = Some(QueuedLoad {
+                map: String::from("res/maps/") + map.as_str(),
+                pos
+            });
+            this.world.transition = transition;
+            Ok(())
+        });
+
+        // Audio
+        methods.add_method_mut("play_sound", |_, this, (sound, speed, volume): (String, Option<f32>, Option<f32>)| {
+            this.world.special_context.play_sounds.push((sound, speed.unwrap_or(1.0), volume.unwrap_or(1.0)));
+            Ok(())
+        });
+
+        // Same as the change_song action, a nil song keeps the current one and only changes speed and volume
+        methods.add_method_mut("change_song", |_, this, (song, speed, volume): (Option<String>, Option<f32>, Option<f32>)| {
+            if let Some(path) = song {
+                let path = PathBuf::from(path);
+                if !path.exists() {
+                    return Err(mlua::Error::RuntimeError(format!("song {} does not exist", path.display())));
+                }
+                this.world.song = Some(Song::new(path));
+                this.world.song.as_mut().unwrap().dirty = true;
+                this.world.song.as_mut().unwrap().reload = true;
+            }
+
+            if let Some(current_song) = &mut this.world.song {
+                if let Some(speed) = speed {
+                    current_song.speed = speed;
+                    current_song.dirty = true;
+                }
+                if let Some(volume) = volume {
+                    current_song.volume = volume;
+                    current_song.dirty = true;
+                }
+                this.player.unlock_song(current_song.name.clone(), current_song.speed);
+            }
             Ok(())
         });
+
+        // Map
+        methods.add_method_mut("set_layer_visible", |_, this, (name, visible): (String, bool)| {
+            let mut found = false;
+            for layer in this.world.layers.iter_mut().filter(|layer| layer.name == name) {
+                layer.draw = visible;
+                found = true;
+            }
+            for layer in this.world.image_layers.iter_mut().filter(|layer| layer.name == name) {
+                layer.draw = visible;
+                found = true;
+            }
+            Ok(found)
+        });
+
+        methods.add_method_mut("set_tile", |_, this, (layer, tileset, tile, x, y): (String, String, i32, u32, u32)| {
+            if x >= this.world.width || y >= this.world.height {
+                return Err(mlua::Error::RuntimeError(format!("tile ({}, {}) is outside the map", x, y)));
+            }
+            if this.world.get_tileset_by_name(&tileset).is_none() {
+                return Err(mlua::Error::RuntimeError(format!("no tileset named \"{}\"", tileset)));
+            }
+            if this.world.get_mut_layer_by_name(&layer).is_none() {
+                return Err(mlua::Error::RuntimeError(format!("no layer named \"{}\"", layer)));
+            }
+            this.world.try_set_tile(&layer, &tileset, tile, x, y).map_err(|_| mlua::Error::RuntimeError("failed to set tile".to_string()))
+        });
     }
 }
 
-// impl UserData for EntityWrapper<'_> {
-//     fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
-//         methods.add_method_mut("test", |_, this, ()| {
-//             this.entity.draw = !this.entity.draw;
-//             Ok(())
-//         });
-//     }
-// }
\ No newline at end of file
+impl UserData for EntityWrapper<'_> {
+    fn add_fields<F: mlua::UserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("id", |_, this| Ok(this.entity.id));
+
+        // Position in pixels
+        fields.add_field_method_get("x", |_, this| Ok(this.entity.x));
+        fields.add_field_method_set("x", |_, this, x: i32| {
+            this.entity.x = x;
+            Ok(())
+        });
+        fields.add_field_method_get("y", |_, this| Ok(this.entity.y));
+        fields.add_field_method_set("y", |_, this, y: i32| {
+            this.entity.y = y;
+            Ok(())
+        });
+
+        fields.add_field_method_get("draw", |_, this| Ok(this.entity.draw));
+        fields.add_field_method_set("draw", |_, this, draw: bool| {
+            this.entity.draw = draw;
+            Ok(())
+        });
+        fields.add_field_method_get("solid", |_, this| Ok(this.entity.solid));
+        fields.add_field_method_set("solid", |_, this, solid: bool| {
+            this.entity.solid = solid;
+            Ok(())
+        });
+
+        // Setting the frame replaces the animation with a single frame, same as the set_animation_frame action
+        fields.add_field_method_get("frame", |_, this| Ok(this.entity.animator.as_ref().map(|animator| animator.frame)));
+        fields.add_field_method_set("frame", |_, this, frame: u32| {
+            this.entity.animator = Some(Animator::new(AnimationFrameData::SingleFrame(frame), this.entity.tileset, 0));
+            Ok(())
+        });
+    }
+
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        // Variables, only literal values can be read since properties need the world to evaluate
+        methods.add_method("get_var", |lua, this, name: String| {
+            let variables = this.entity.variables.borrow();
+            let value = match variables.get(&name) {
+                Some(VariableValue::LitInt(i)) => mlua::Value::Integer(*i as mlua::Integer),
+                Some(VariableValue::LitFloat(f)) => mlua::Value::Number(*f as mlua::Number),
+                Some(VariableValue::LitBool(b)) => mlua::Value::Boolean(*b),
+                Some(VariableValue::LitString(s)) => mlua::Value::String(lua.create_string(s)?),
+                _ => mlua::Value::Nil
+            };
+            Ok(value)
+        });
+
+        methods.add_method_mut("set_var", |_, this, (name, value): (String, mlua::Value)| {
+            let value = match value {
+                mlua::Value::Integer(i) => VariableValue::LitInt(i as i32),
+                mlua::Value::Number(f) => VariableValue::LitFloat(f as f32),
+                mlua::Value::Boolean(b) => VariableValue::LitBool(b),
+                mlua::Value::String(s) => VariableValue::LitString(s.to_str()?.to_string()),
+                _ => return Err(mlua::Error::RuntimeError(format!("variable {} must be a number, boolean or string", name)))
+            };
+            this.entity.set_variable(name, value);
+            Ok(())
+        });
+
+        // Movement
+        methods.add_method_mut("walk", |_, this, direction: String| {
+            let direction = Direction::from_str(&direction).map_err(|_| mlua::Error::RuntimeError(format!("invalid direction \"{}\"", direction)))?;
+            *this.walk = Some(direction);
+            Ok(())
+        });
+
+        methods.add_method("is_moving", |_, this, ()| {
+            Ok(this.entity.movement.as_ref().is_some_and(|movement| movement.moving))
+        });
+    }
+}
@@ -1,18 +1,20 @@
 extern crate json;
 
-use std::{path::PathBuf, sync::Arc, collections::HashMap, fs::File};
+use std::{path::PathBuf, sync::Arc, collections::HashMap};
 
 use audio::{SoundEffectBank, Song};
+use controls::{Action, Bindings};
 use debug::{Debug, ProfileInfo};
 use game::{Input, RenderState, QueuedLoad, WarpPos, IntProperty, LevelPropertyType};
 use player::Player;
+use replay::{Recording, Replay};
 use rodio::{OutputStream, Sink};
 use save::{SaveInfo, SaveData};
-use sdl2::{image::{InitFlag, LoadSurface}, keyboard::Keycode, pixels::Color, rect::Rect, surface::Surface, sys::{SDL_Delay, SDL_GetTicks}, video::FullscreenType};
+use sdl2::{image::{InitFlag, LoadSurface}, pixels::{Color, PixelFormatEnum}, rect::Rect, render::TextureCreator, surface::Surface, sys::{SDL_Delay, SDL_GetTicks}, video::FullscreenType};
 use texture::Texture;
 use transitions::{Transition, TransitionType};
 use ui::{Ui, MenuType, Font};
-use world::World;
+use world::{RandomState, World};
 
 use crate::lua::ScriptingContext;
 
@@ -21,21 +23,31 @@ extern crate sdl2;
 mod actions;
 mod ai;
 mod audio;
+mod check;
+mod cli;
+mod controls;
 mod debug;
 mod effect;
 mod entity;
 mod game;
+mod headless;
+mod lint;
 mod loader;
 mod lua;
+#[cfg(test)]
+mod map_tests;
 // mod optimize;
 mod particles;
+mod paths;
 mod player;
+mod replay;
 mod save;
 mod screen_event;
 mod tiles;
 mod transitions;
 mod texture;
 mod ui;
+mod warps;
 mod world;
 
 pub const START_MAP: &str = "res/maps/bedroom.tmx";
@@ -57,6 +69,27 @@ fn find_sdl_gl_driver() -> Option<u32> {
 }
 
 fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    paths::init(cli::take_option(&mut args, "--data-dir").map(PathBuf::from));
+    let seed = cli::take_seed(&mut args);
+    let record_path = cli::take_option(&mut args, "--record").map(PathBuf::from);
+    let replay_path = cli::take_option(&mut args, "--replay").map(PathBuf::from);
+    if let Some(code) = cli::run(&args, seed) {
+        std::process::exit(code);
+    }
+
+    let mut replay = replay_path.map(|path| Replay::new(Recording::load(&path).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    })));
+    // A replay only plays back the same way with the seed it was recorded with
+    let seed = match &replay {
+        Some(replay) => replay.recording.seed,
+        None => seed.unwrap_or_else(rand::random)
+    };
+    println!("Random seed: {}", seed);
+    let mut recording = record_path.as_ref().map(|_| Recording::new(seed));
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let _image_context = sdl2::image::init(InitFlag::PNG | InitFlag::JPG);
@@ -91,12 +124,16 @@ fn main() {
     //ui.init(&mut sfx);
 
     let mut save_info = SaveInfo::read_or_create_new().expect("failed to read or create save data, the .saves file may be missing or corrupted");
+    ui.load_save_thumbnails(&save_info, &texture_creator);
 
     let mut player = Player::new(&texture_creator);
 
     let mut input = Input::new();
+    // Recordings store keys, so a replay only matches with the bindings it was recorded with
+    input.bindings = Bindings::read_or_create(&paths::config());
 
     let mut world = World::new(&texture_creator, &render_state);
+    world.random = RandomState::new(seed);
     let mut song = Song::new(PathBuf::from(MAIN_MENU_MUSIC));
     song.default_speed = MAIN_MENU_MUSIC_SPEED;
     song.speed = MAIN_MENU_MUSIC_SPEED;
@@ -108,7 +145,7 @@ fn main() {
     let mut scripts = ScriptingContext::new();
 
     world.onload(&player, &sink, &render_state, &mut scripts);
-    scripts.on_load(&mut world);
+    scripts.on_load(&mut world, &mut player);
     if let Some(def) = world.default_pos {
         player.set_x(def.0 * 16);
         player.set_y(def.1 * 16);
@@ -129,26 +166,42 @@ fn main() {
         enable_debug_overlay: false,
         mini_font: Font::new_mini(Texture::from_file(&PathBuf::from(ui::MINIFONT_PATH), &texture_creator).expect("failed to load debug font"))
     };
+    let mut tick: u64 = 0;
 
     'mainloop: loop {
         for event in events.poll_iter() {
             use sdl2::event::Event;
             match event {
                 Event::Quit { .. } => break 'mainloop,
-                Event::KeyDown { keycode, repeat, .. } => {
+                // The keyboard is ignored while a replay is playing
+                Event::KeyDown { keycode, repeat, .. } if replay.is_none() => {
                     if keycode.is_some() && !repeat {
                         input.pressed(keycode.unwrap());
+                        if let Some(recording) = &mut recording {
+                            recording.record(tick, keycode.unwrap(), true);
+                        }
                     }
                 },
-                Event::KeyUp { keycode, .. } => {
+                Event::KeyUp { keycode, .. } if replay.is_none() => {
                     if keycode.is_some() {
                         input.released(keycode.unwrap());
+                        if let Some(recording) = &mut recording {
+                            recording.record(tick, keycode.unwrap(), false);
+                        }
                     }
                 },
                 _ => ()
             }
         }
 
+        if let Some(playing) = &mut replay {
+            playing.apply(tick, &mut input);
+            if playing.finished() {
+                println!("Replay finished on tick {}", tick);
+                replay = None;
+            }
+        }
+
         canvas.set_draw_color(Color::RGBA(0, 0, 0, 255));
         canvas.clear();
         if !ui.clear {
@@ -162,48 +215,97 @@ fn main() {
         ui.update(&input, &mut player, &mut world, &save_info, &sink, &mut sfx);
 
         if world.special_context.write_save_to_pending {
-            let save_data = SaveData::create(&player);
-            save_data.save(world.special_context.pending_save as u32, &PathBuf::from("saves/".to_string() + &world.special_context.pending_save.to_string() + ".save"), &mut save_info).expect("failed to save game data");
+            let save_path = paths::save_slot(world.special_context.pending_save as u32);
+
+            // Draw the world without the menu into the transition screenshot to use as the slot thumbnail
+            if let Some(mut screenshot) = world.transition_context.screenshot.take() {
+                let query = screenshot.query();
+                let mut pixels = None;
+                canvas.with_texture_canvas(&mut screenshot, |tex_canvas| {
+                    tex_canvas.set_draw_color(world.background_color);
+                    tex_canvas.set_blend_mode(sdl2::render::BlendMode::None);
+                    tex_canvas.clear();
+                    tex_canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+
+                    if world.looping {
+                        world.draw_looping(tex_canvas, &player, &render_state);
+                    } else {
+                        world.draw(tex_canvas, &player, &render_state);
+                    }
+
+                    pixels = tex_canvas.read_pixels(None, PixelFormatEnum::RGBA32).ok();
+                }).unwrap();
+                world.transition_context.screenshot = Some(screenshot);
+
+                if let Some(mut pixels) = pixels {
+                    if let Err(e) = save::write_thumbnail(&mut pixels, query.width, query.height, &save::thumbnail_path(&save_path)) {
+                        eprintln!("Warning: failed to write save thumbnail: {}", e);
+                    }
+                }
+            }
+
+            let save_data = SaveData::create(&player, &world, scripts.persist_data());
+            save_data.save(world.special_context.pending_save as u32, &save_path, &mut save_info).expect("failed to save game data");
+            ui.load_save_thumbnails(&save_info, &texture_creator);
             world.special_context.write_save_to_pending = false
         }
 
         if world.special_context.new_game {
+            let mut load_error = None;
             if let Some(load) = world.special_context.pending_load {
-                let file = File::open(&PathBuf::from("saves/".to_string() + &load.to_string() + ".save")).expect("failed to open save file");
-                let save_data: SaveData = serde_cbor::from_reader(&file).expect("failed to read save data. data may be corrupted");
-                player = save_data.get_player(&texture_creator);
+                match SaveData::load(&paths::save_slot(load as u32)) {
+                    Ok(save_data) => {
+                        player = save_data.get_player(&texture_creator);
+                        world.global_flags = save_data.get_global_flags();
+                        scripts.load_persist(&save_data.persist);
+                    },
+                    Err(e) => {
+                        eprintln!("Error: failed to load save {}: {}", load, e);
+                        load_error = Some(e);
+                    }
+                }
             } else {
                 player = Player::new(&texture_creator);
+                scripts.reset_persist();
             }
             world.special_context.pending_load = None;
-            
-
-            world.queued_load = Some(QueuedLoad {
-                map: String::from(START_MAP),
-                pos: WarpPos { x: IntProperty::Level(LevelPropertyType::DefaultX), y: IntProperty::Level(LevelPropertyType::DefaultY) }
-            });
-            world.transition = Some(Transition::new(TransitionType::FadeScreenshot, 2, 0, true, 32, false));
             world.special_context.new_game = false;
-            world.paused = false;
+
+            if let Some(e) = load_error {
+                // Stay on the title screen and explain what went wrong
+                ui.show_menu(MenuType::LoadError(e.message()));
+                ui.menu_state.menu_should_close = false;
+                ui.menu_state.menu_screenshot = false;
+                ui.menu_state.close_on_x = false;
+                world.paused = true;
+            } else {
+                world.queued_load = Some(QueuedLoad {
+                    map: String::from(START_MAP),
+                    pos: WarpPos { x: IntProperty::Level(LevelPropertyType::DefaultX), y: IntProperty::Level(LevelPropertyType::DefaultY) }
+                });
+                world.transition = Some(Transition::new(TransitionType::FadeScreenshot, 2, 0, true, 32, false));
+                world.paused = false;
+            }
         }
 
         if !ui.open {
             if !world.paused {
                 player.update(&input, &mut world, &mut sfx);
+                player.stats.playtime += TICK_INTERVAL as u64;
             }
-            world.update(&mut player, &mut sfx, &sink, &input, &mut render_state);
+            world.update(&mut player, &mut sfx, &sink, &input, &mut render_state, &mut scripts);
             if player.effect_just_changed {
                 player.effect_just_changed = false;
             }
         }
 
         if ui.open && matches!(ui.menu_state.current_menu, MenuType::MusicPlayer) {
-            world.update(&mut player, &mut sfx, &sink, &input, &mut render_state);
+            world.update(&mut player, &mut sfx, &sink, &input, &mut render_state, &mut scripts);
         }
 
-        scripts.on_update(&mut world);
+        scripts.on_update(&mut world, &mut player, &input);
 
-        if input.get_just_pressed(Keycode::F4) {
+        if input.get_action_just_pressed(Action::Fullscreen) {
             if render_state.fullscreen {
                 canvas.set_scale(2.0, 2.0).unwrap();
                 canvas.window_mut().set_fullscreen(FullscreenType::Off).unwrap();
@@ -294,93 +396,23 @@ fn main() {
             ui.menu_state.menu_screenshot = false;
         }
 
-        debug.draw(&mut canvas, &ui, &player, &render_state);
+        debug.draw(&mut canvas, &ui, &player, &render_state, &scripts.errors);
 
         canvas.present();
 
         if world.queued_load.is_some() && world.transition.is_some() && world.transition.as_ref().unwrap().progress >= 100 {
-            let transition = world.transition.clone();
-            let map = world.queued_load.as_ref().unwrap().map.clone();
-            let name = PathBuf::from(map.clone()).file_stem().map(|f| f.to_str().unwrap_or("error").to_string());
-            //let default = world.default_pos.clone();
-            player.moving = false;
-            player.move_timer = 0;
-            let warp_pos = world.queued_load.as_ref().unwrap().pos.clone();
-
-            let mut skip_end = false;
-
-            if let Some(new_name) = name {
-                if (new_name != world.name) || world.special_context.reload_on_warp {
-                    world.special_context.reload_on_warp = false;
-                    let mut old_song = None;
-                    if let Some(song) = &world.song {
-                        old_song = Some(song.path.clone());
-                    }
-                    let old_flags = std::mem::replace(&mut world.global_flags, HashMap::new());
-                    world = World::load_from_file(&map, &texture_creator, &mut Some(world), &render_state).expect("failed to load map");
-                    world.global_flags = old_flags;
-                    world.transition = transition;
-
-                    if let Some(song) = &mut world.song {
-                        if let Some(transition) = &world.transition {
-                            if transition.fade_music {
-                                song.volume = 0.0;
-                            }
-
-                            if let Some(old_song) = old_song {
-                                if transition.reset_same_music && old_song == song.path {
-                                    song.reload(&sink);
-                                }
-                            }
-                        }
-                    }
-                    
-                    //world.onload(&player, &sink);
-                } else {
-                    world.reset();
-                    world.transition_context.take_screenshot = true;
-                }
+            let (loaded, to_menu) = load_queued_map(world, &mut player, &mut scripts, &sink, &render_state, &texture_creator);
+            world = loaded;
+
+            if to_menu {
+                ui.menu_state.current_menu = MenuType::MainMenu;
+                ui.open = true;
+                ui.clear = true;
+                ui.menu_state.button_id = 2;
             } else {
-                if map == "" {
-                    let old_flags = std::mem::replace(&mut world.global_flags, HashMap::new());
-                    world = World::new(&texture_creator, &render_state);
-                    world.global_flags = old_flags;
-                    world.transition = transition;
-                    let mut song = Song::new(PathBuf::from(MAIN_MENU_MUSIC));
-                    song.default_speed = MAIN_MENU_MUSIC_SPEED;
-                    song.speed = MAIN_MENU_MUSIC_SPEED;
-                    song.volume = MAIN_MENU_MUSIC_VOLUME;
-                    song.default_volume = MAIN_MENU_MUSIC_VOLUME;
-                    song.dirty = true;
-                    world.song = Some(song);
-                    //world.onload(&player, &sink);
-
-                    ui.menu_state.current_menu = MenuType::MainMenu;
-                    ui.open = true;
-                    ui.clear = true;
-                    ui.menu_state.button_id = 2;
-                    world.paused = true;
-                    skip_end = true;
-                }
-            }
-
-            if let Some(x) = warp_pos.x.get(Some(&player), Some(&world)) {
-                player.set_x(x * 16);
-            }
-            if let Some(y) = warp_pos.y.get(Some(&player), Some(&world)) {
-                player.set_y(y * 16);
-            }
-
-            world.onload(&player, &sink, &render_state, &mut scripts);
-            scripts.on_load(&mut world);
-
-            if !skip_end {
-                player.frozen = false;
                 ui.clear = false;
                 ui.open = false;
             }
-
-            player.on_level_transition(&mut world);
         }
 
         if ui.menu_state.should_quit {
@@ -393,10 +425,108 @@ fn main() {
             // next_time += TICK_INTERVAL;
             next_time = SDL_GetTicks() + TICK_INTERVAL;
         }
+
+        tick += 1;
     }
+
+    if let (Some(recording), Some(path)) = (recording, record_path) {
+        match recording.save(&path) {
+            Ok(()) => println!("Recorded {} ticks to {}", tick, path.display()),
+            Err(e) => eprintln!("Warning: could not write recording {}: {}", path.display(), e)
+        }
+    }
+}
+
+/// Loads the map a warp queued once its transition has covered the screen<br>
+/// Returns the new world and whether the warp went back to the title screen instead of a map
+pub fn load_queued_map<'a, T>(mut world: World<'a>, player: &mut Player<'a>, scripts: &mut ScriptingContext, sink: &Sink, render_state: &RenderState, creator: &'a TextureCreator<T>) -> (World<'a>, bool) {
+    let transition = world.transition.clone();
+    let map = world.queued_load.as_ref().unwrap().map.clone();
+    let name = PathBuf::from(map.clone()).file_stem().map(|f| f.to_str().unwrap_or("error").to_string());
+    //let default = world.default_pos.clone();
+    player.moving = false;
+    player.move_timer = 0;
+    let warp_pos = world.queued_load.as_ref().unwrap().pos.clone();
+
+    let mut to_menu = false;
+
+    if let Some(new_name) = name {
+        if (new_name != world.name) || world.special_context.reload_on_warp {
+            world.special_context.reload_on_warp = false;
+            let mut old_song = None;
+            if let Some(song) = &world.song {
+                old_song = Some(song.path.clone());
+            }
+            scripts.on_unload(&mut world, player);
+            let old_flags = std::mem::replace(&mut world.global_flags, HashMap::new());
+            world = World::load_from_file(&map, creator, &mut Some(world), render_state).expect("failed to load map");
+            world.global_flags = old_flags;
+            world.transition = transition;
+
+            if let Some(song) = &mut world.song {
+                if let Some(transition) = &world.transition {
+                    if transition.fade_music {
+                        song.volume = 0.0;
+                    }
+
+                    if let Some(old_song) = old_song {
+                        if transition.reset_same_music && old_song == song.path {
+                            song.reload(sink);
+                        }
+                    }
+                }
+            }
+            
+            //world.onload(&player, &sink);
+        } else {
+            // Warping within the same map runs _onload again, so the script sees it unload first
+            scripts.on_unload(&mut world, player);
+            world.reset();
+            world.transition_context.take_screenshot = true;
+        }
+    } else {
+        if map == "" {
+            scripts.on_unload(&mut world, player);
+            let old_flags = std::mem::replace(&mut world.global_flags, HashMap::new());
+            let random = world.random.clone();
+            world = World::new(creator, render_state);
+            world.global_flags = old_flags;
+            world.random = random;
+            world.transition = transition;
+            let mut song = Song::new(PathBuf::from(MAIN_MENU_MUSIC));
+            song.default_speed = MAIN_MENU_MUSIC_SPEED;
+            song.speed = MAIN_MENU_MUSIC_SPEED;
+            song.volume = MAIN_MENU_MUSIC_VOLUME;
+            song.default_volume = MAIN_MENU_MUSIC_VOLUME;
+            song.dirty = true;
+            world.song = Some(song);
+            //world.onload(&player, &sink);
+
+            world.paused = true;
+            to_menu = true;
+        }
+    }
+
+    if let Some(x) = warp_pos.x.get(Some(&*player), Some(&world)) {
+        player.set_x(x * 16);
+    }
+    if let Some(y) = warp_pos.y.get(Some(&*player), Some(&world)) {
+        player.set_y(y * 16);
+    }
+
+    world.onload(player, sink, render_state, scripts);
+    scripts.on_load(&mut world, player);
+
+    if !to_menu {
+        player.frozen = false;
+    }
+
+    player.on_level_transition(&mut world);
+
+    (world, to_menu)
 }
 
-fn clamp_camera(render_state: &mut RenderState, world: &World, player: &Player) {
+pub fn clamp_camera(render_state: &mut RenderState, world: &World, player: &Player) {
     render_state.offset = (-player.x + (render_state.screen_extents.0 as i32 / 2) - 8, -player.y + (render_state.screen_extents.1 as i32 / 2) - 16);
 
     if world.clamp_horizontal() {
@@ -440,7 +570,7 @@ fn clamp_camera(render_state: &mut RenderState, world: &World, player: &Player)
     render_state.offset.1 += render_state.camera_slide_offset.1;
 }
 
-const TICK_INTERVAL: u32 = 16;
+pub const TICK_INTERVAL: u32 = 16;
 
 unsafe fn time_left(next_time: u32) -> u32 {
     let now = SDL_GetTicks();
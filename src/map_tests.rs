@@ -0,0 +1,117 @@
+use sdl2::keyboard::Keycode;
+
+use crate::{game::Direction, headless::{HeadlessContext, Simulation, Step}, replay::Recording};
+
+/// A small open room with a counter at (5, 3), a lock at (4, 5) and a warp pad at (2, 5)
+const HARNESS_MAP: &str = "tests/maps/harness.tmx";
+const SEED: u64 = 1;
+
+fn harness(context: &HeadlessContext) -> Simulation {
+    Simulation::new(context, HARNESS_MAP, SEED).unwrap()
+}
+
+#[test]
+fn loads_at_default_position() {
+    let context = HeadlessContext::new().unwrap();
+    let simulation = harness(&context);
+
+    assert_eq!(simulation.world.name, "harness");
+    assert_eq!(simulation.tile(), (2, 2));
+    assert!(simulation.entity_at(5, 3).is_some());
+    assert!(simulation.entity_at(4, 5).is_some());
+}
+
+#[test]
+fn use_trigger_sets_flag() {
+    let context = HeadlessContext::new().unwrap();
+    let mut simulation = harness(&context);
+
+    simulation.play(&[
+        Step::Walk(Direction::Right, 2),
+        Step::Tap(Keycode::Z),
+        Step::Wait(1),
+        Step::Tap(Keycode::Z),
+        Step::Wait(1)
+    ]);
+
+    assert_eq!(simulation.tile(), (4, 2));
+    assert_eq!(simulation.flag("presses"), Some(2));
+}
+
+#[test]
+fn condition_fails_before_enough_presses() {
+    let context = HeadlessContext::new().unwrap();
+    let mut simulation = harness(&context);
+
+    simulation.play(&[
+        Step::Walk(Direction::Right, 2),
+        Step::Tap(Keycode::Z),
+        Step::Walk(Direction::Down, 1),
+        Step::Tap(Keycode::Down),
+        Step::Wait(1)
+    ]);
+
+    assert_eq!(simulation.flag("presses"), Some(1));
+    assert_eq!(simulation.flag("unlocked"), None);
+}
+
+#[test]
+fn condition_passes_after_enough_presses() {
+    let context = HeadlessContext::new().unwrap();
+    let mut simulation = harness(&context);
+
+    simulation.play(&[
+        Step::Walk(Direction::Right, 2),
+        Step::Tap(Keycode::Z),
+        Step::Tap(Keycode::Z),
+        Step::Walk(Direction::Down, 1),
+        Step::Tap(Keycode::Down),
+        Step::Wait(1)
+    ]);
+
+    assert_eq!(simulation.flag("unlocked"), Some(1));
+}
+
+#[test]
+fn walk_trigger_warps() {
+    let context = HeadlessContext::new().unwrap();
+    let mut simulation = harness(&context);
+
+    simulation.play(&[
+        Step::Walk(Direction::Down, 2),
+        Step::Wait(30)
+    ]);
+
+    assert_eq!(simulation.world.name, "bedroom");
+    assert_eq!(simulation.tile(), (7, 7));
+    // Global flags survive the warp, local ones belong to the old map
+    assert_eq!(simulation.global_flag("left_harness"), Some(1));
+    assert_eq!(simulation.flag("presses"), None);
+}
+
+#[test]
+fn replay_matches_recorded_session() {
+    let context = HeadlessContext::new().unwrap();
+    let mut recorded = harness(&context);
+    recorded.recording = Some(Recording::new(SEED));
+
+    recorded.play(&[
+        Step::Walk(Direction::Right, 2),
+        Step::Tap(Keycode::Z),
+        Step::Tap(Keycode::Z),
+        Step::Walk(Direction::Down, 1),
+        Step::Tap(Keycode::Down),
+        Step::Wait(1)
+    ]);
+
+    // Round trip through the file format
+    let recording = Recording::parse(&recorded.recording.take().unwrap().to_string()).unwrap();
+    let mut replayed = Simulation::new(&context, HARNESS_MAP, recording.seed).unwrap();
+    replayed.replay(recording);
+    replayed.run((recorded.ticks - replayed.ticks) as u32);
+
+    assert_eq!(replayed.ticks, recorded.ticks);
+    assert_eq!(replayed.tile(), recorded.tile());
+    assert_eq!(replayed.flag("presses"), Some(2));
+    assert_eq!(replayed.flag("unlocked"), Some(1));
+}
@@ -1,7 +1,7 @@
 use std::{collections::VecDeque, fmt::Debug};
 
 use json::JsonValue;
-use rand::{distributions::uniform::SampleUniform, Rng};
+use rand::{distributions::uniform::SampleUniform, rngs::StdRng, Rng};
 use sdl2::{rect::Rect, render::{Canvas, RenderTarget}};
 
 use crate::{game::RenderState, world::World};
@@ -14,11 +14,11 @@ pub enum ParticleValue<T: SampleUniform + Copy + PartialOrd> {
 }
 
 impl<T: SampleUniform + Copy + PartialOrd + Debug> ParticleValue<T> {
-    pub fn get(&self) -> T {
+    pub fn get(&self, rng: &mut StdRng) -> T {
         match self {
             Self::Value(v) => *v,
-            Self::RandRange(min, max) => rand::thread_rng().gen_range(*min..*max),
-            //Self::RandRangeNormal(min, max) => rand::thread_rng().gen_range(range)
+            Self::RandRange(min, max) => rng.gen_range(*min..*max),
+            //Self::RandRangeNormal(min, max) => rng.gen_range(range)
         }
     }
 }
@@ -54,30 +54,30 @@ impl ParticleEmitter {
         }
     }
 
-    pub fn add_particle(&mut self) {
+    pub fn add_particle(&mut self, rng: &mut StdRng) {
         let particle = Particle {
             active: true,
-            pos: (self.pos.0 as f32 + self.pos_offset.0.get(), self.pos.1 as f32 + self.pos_offset.1.get()),
-            vel: (self.init_vel.0.get(), self.init_vel.1.get()),
-            acc: (self.init_acc.0.get(), self.init_acc.1.get()),
-            stagnate: self.stagnate.get(),
-            life: self.init_life.get(),
+            pos: (self.pos.0 as f32 + self.pos_offset.0.get(rng), self.pos.1 as f32 + self.pos_offset.1.get(rng)),
+            vel: (self.init_vel.0.get(rng), self.init_vel.1.get(rng)),
+            acc: (self.init_acc.0.get(rng), self.init_acc.1.get(rng)),
+            stagnate: self.stagnate.get(rng),
+            life: self.init_life.get(rng),
             size: self.size,
-            tx_coord: (self.init_tx_coord.0.get(), self.init_tx_coord.1.get()),
-            tx_vel: (self.init_tx_vel.0.get(), self.init_tx_vel.1.get())
+            tx_coord: (self.init_tx_coord.0.get(rng), self.init_tx_coord.1.get(rng)),
+            tx_vel: (self.init_tx_vel.0.get(rng), self.init_tx_vel.1.get(rng))
         };
 
         self.particles.push_back(particle);
     }
 
-    pub fn update(&mut self, pos: (i32, i32)) {
+    pub fn update(&mut self, pos: (i32, i32), rng: &mut StdRng) {
         self.pos = pos;
 
         self.timer -= 1;
         if self.timer <= 0 {
-            self.timer = self.freq as i32 + rand::thread_rng().gen_range(0..=self.freq_rand);
+            self.timer = self.freq as i32 + rng.gen_range(0..=self.freq_rand);
 
-            self.add_particle();
+            self.add_particle(rng);
         }
 
         if self.particles.is_empty() {
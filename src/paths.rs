@@ -0,0 +1,62 @@
+use std::{fs, path::PathBuf, sync::OnceLock};
+
+/// Folder created inside the platform data directory
+const DATA_DIR_NAME: &str = "yume";
+const SAVES_DIR_NAME: &str = "saves";
+const SAVE_INFO_NAME: &str = ".saves";
+const CONFIG_NAME: &str = "config.json";
+
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directory that saves and config are kept in<br>
+/// Uses the platform user data directory unless `data_dir` is given, or the working directory
+/// if it has a `saves/` folder from an older version and the data directory has none
+pub fn init(data_dir: Option<PathBuf>) {
+    let dir = data_dir.unwrap_or_else(|| {
+        match dirs::data_dir() {
+            Some(dir) => {
+                let dir = dir.join(DATA_DIR_NAME);
+                if !dir.join(SAVES_DIR_NAME).exists() && PathBuf::from(SAVES_DIR_NAME).is_dir() {
+                    PathBuf::from(".")
+                } else {
+                    dir
+                }
+            },
+            None => {
+                eprintln!("Warning: no user data directory found, using the working directory");
+                PathBuf::from(".")
+            }
+        }
+    });
+    // stderr, so tools that write to stdout like --warp-graph stay clean
+    eprintln!("Data directory: {}", dir.display());
+
+    if let Err(e) = fs::create_dir_all(dir.join(SAVES_DIR_NAME)) {
+        eprintln!("Warning: could not create data directory {}: {}", dir.display(), e);
+    }
+
+    if DATA_DIR.set(dir).is_err() {
+        eprintln!("Warning: data directory was already set");
+    }
+}
+
+pub fn data_dir() -> &'static PathBuf {
+    DATA_DIR.get().expect("data directory used before paths::init")
+}
+
+pub fn saves_dir() -> PathBuf {
+    data_dir().join(SAVES_DIR_NAME)
+}
+
+/// The index of all save slots
+pub fn save_info() -> PathBuf {
+    saves_dir().join(SAVE_INFO_NAME)
+}
+
+pub fn save_slot(slot: u32) -> PathBuf {
+    saves_dir().join(slot.to_string() + ".save")
+}
+
+pub fn config() -> PathBuf {
+    data_dir().join(CONFIG_NAME)
+}
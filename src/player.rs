@@ -1,10 +1,10 @@
 use std::{path::PathBuf, collections::HashMap};
 
-use sdl2::{render::{TextureCreator, RenderTarget, Canvas}, rect::Rect, keyboard::Keycode};
+use sdl2::{render::{TextureCreator, RenderTarget, Canvas}, rect::Rect};
 
 use serde_derive::{Serialize, Deserialize};
 
-use crate::{audio::SoundEffectBank, effect::Effect, game::{Direction, Input, IntProperty, RenderState, WarpPos}, texture::Texture, tiles::SpecialTile, transitions::{Transition, TransitionType}, world::World};
+use crate::{audio::SoundEffectBank, controls::Action, effect::Effect, game::{Direction, Input, IntProperty, RenderState, WarpPos}, texture::Texture, tiles::SpecialTile, transitions::{Transition, TransitionType}, world::World};
 
 pub const SWITCH_EFFECT_ANIMATION_SPEED: u32 = 2;
 pub const WAKE_UP_TIMER_MAX: u32 = 100;
@@ -58,7 +58,9 @@ pub struct Player<'a> {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Statistics {
     pub steps: u64,
-    pub times_slept: u32
+    pub times_slept: u32,
+    /// Milliseconds spent in game, not counting menus
+    pub playtime: u64
 }
 
 pub struct AnimationOverrideController {
@@ -164,7 +166,8 @@ impl Statistics {
     pub fn new() -> Self {
         Self {
             steps: 0,
-            times_slept: 0
+            times_slept: 0,
+            playtime: 0
         }
     }
 }
@@ -572,17 +575,14 @@ impl<'a> Player<'a> {
     }
 
     pub fn movement_check(&mut self, input: &Input, world: &mut World, force: bool) -> bool {
-        use Keycode::*;
+        use Direction::*;
         if self.disable_player_input {
             return false;
         }
 
         let directions_pressed: Vec<Direction> = [Up, Down, Left, Right]
-            .iter()
-            .filter(|key| input.get_pressed(**key))
-            .map(Direction::from_key)
-            .filter(Option::is_some)
-            .map(|x| x.unwrap())
+            .into_iter()
+            .filter(|direction| input.get_action_pressed(direction.action()))
             .collect();
 
         if directions_pressed.len() > 1 {
@@ -590,12 +590,12 @@ impl<'a> Player<'a> {
             let last_pressed = directions_pressed.iter()
                 .find(|dir| **dir == self.last_direction.unwrap());
             if let Some(last) = last_pressed {
-                self.move_player(*last, world, force, input.get_just_pressed(last.to_key().unwrap_or(Keycode::PrintScreen)));
+                self.move_player(*last, world, force, input.get_action_just_pressed(last.action()));
                 return true;
             }
         } else if directions_pressed.len() == 1 {
             let direction = directions_pressed.first().unwrap();
-            self.move_player(*direction, world, force, input.get_just_pressed(direction.to_key().unwrap_or(Keycode::PrintScreen)));
+            self.move_player(*direction, world, force, input.get_action_just_pressed(direction.action()));
             return true;
         }
 
@@ -767,10 +767,10 @@ impl<'a> Player<'a> {
 
     pub fn update(&mut self, input: &Input, world: &mut World, sfx: &mut SoundEffectBank) {
         {
-            use Keycode::*;
-            for key in [Up, Down, Left, Right, W, A, S, D].into_iter() {
-                if input.get_just_pressed(key) {
-                    self.last_direction = Direction::from_key(&key);
+            use Direction::*;
+            for direction in [Up, Down, Left, Right].into_iter() {
+                if input.get_action_just_pressed(direction.action()) {
+                    self.last_direction = Some(direction);
                     break;
                 }
             }
@@ -932,7 +932,7 @@ impl<'a> Player<'a> {
             }
         } else {
             self.movement_check(input, world, false);
-            if input.get_just_pressed(Keycode::Z) {
+            if input.get_action_just_pressed(Action::Confirm) {
                 let pos = self.get_standing_tile();
                 world.interactions.push(crate::world::Interaction::Use(pos.0 as i32 + self.facing.x(), pos.1 as i32 + self.facing.y()));
                 if self.animation_override_controller.sit_animation {
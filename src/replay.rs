@@ -0,0 +1,119 @@
+use std::{fs, io, path::Path};
+
+use sdl2::keyboard::Keycode;
+
+use crate::game::Input;
+
+/// A key going down or up on a tick
+#[derive(Clone, Copy)]
+pub struct KeyEvent {
+    pub tick: u64,
+    pub key: Keycode,
+    pub pressed: bool
+}
+
+/// The seed and every key press and release of a session<br>
+/// Saved as text, a `seed <n>` line followed by one `<tick> press|release <key name>` line per event
+#[derive(Clone)]
+pub struct Recording {
+    pub seed: u64,
+    pub events: Vec<KeyEvent>
+}
+
+impl Recording {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            events: Vec::new()
+        }
+    }
+
+    pub fn record(&mut self, tick: u64, key: Keycode, pressed: bool) {
+        self.events.push(KeyEvent { tick, key, pressed });
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut lines = source.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let seed = match lines.next() {
+            Some((_, line)) => line.trim().strip_prefix("seed ").and_then(|seed| seed.trim().parse::<u64>().ok()).ok_or(format!("line 1: expected `seed <n>`, found \"{}\"", line))?,
+            None => return Err("recording is empty".to_string())
+        };
+
+        let mut recording = Self::new(seed);
+        for (i, line) in lines {
+            // Key names can contain spaces, like `Left Alt`
+            let mut parts = line.trim().splitn(3, ' ');
+            let tick = parts.next().and_then(|tick| tick.parse::<u64>().ok()).ok_or(format!("line {}: expected a tick number", i + 1))?;
+            let pressed = match parts.next() {
+                Some("press") => true,
+                Some("release") => false,
+                _ => return Err(format!("line {}: expected press or release", i + 1))
+            };
+            let name = parts.next().unwrap_or("");
+            let key = Keycode::from_name(name).ok_or(format!("line {}: unknown key \"{}\"", i + 1, name))?;
+
+            if recording.events.last().is_some_and(|last| last.tick > tick) {
+                return Err(format!("line {}: tick {} is before the previous event", i + 1, tick));
+            }
+            recording.record(tick, key, pressed);
+        }
+
+        Ok(recording)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        Self::parse(&source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl std::fmt::Display for Recording {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "seed {}", self.seed)?;
+        for event in self.events.iter() {
+            writeln!(f, "{} {} {}", event.tick, if event.pressed { "press" } else { "release" }, event.key.name())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Feeds a recording into the input in place of real key events
+pub struct Replay {
+    pub recording: Recording,
+    next: usize
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            next: 0
+        }
+    }
+
+    /// Sends every event recorded up to `tick` to the input<br>
+    /// Call before the tick is run, the same place key events are polled
+    pub fn apply(&mut self, tick: u64, input: &mut Input) {
+        while let Some(event) = self.recording.events.get(self.next) {
+            if event.tick > tick {
+                break;
+            }
+
+            if event.pressed {
+                input.pressed(event.key);
+            } else {
+                input.released(event.key);
+            }
+            self.next += 1;
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next >= self.recording.events.len()
+    }
+}
@@ -1,19 +1,39 @@
-use std::{collections::BTreeMap, error::Error, fs::File, path::{Path, PathBuf}};
+use std::{collections::{hash_map::DefaultHasher, BTreeMap, HashMap}, error::Error, fmt::Display, fs::{self, File}, hash::{Hash, Hasher}, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
 
-use rand::Rng;
-use sdl2::render::TextureCreator;
+use sdl2::{image::SaveSurface, pixels::PixelFormatEnum, render::{BlendMode, TextureCreator}, surface::Surface};
+use serde_cbor::Value;
 use serde_derive::{Serialize, Deserialize};
 
-use crate::{effect::Effect, player::{MenuTheme, Player, Statistics}};
+use crate::{effect::Effect, paths, player::{MenuTheme, Player, Statistics}, world::World};
+
+/// How many older copies of each save slot are kept as `N.save.bak1`, `N.save.bak2`, ...
+pub const SAVE_BACKUPS: u32 = 3;
+
+pub const THUMBNAIL_WIDTH: u32 = 64;
+pub const THUMBNAIL_HEIGHT: u32 = 48;
+
+/// The version written into new save files<br>
+/// Bump this and add an entry to `MIGRATIONS` whenever `SaveData` changes shape
+pub const SAVE_VERSION: u32 = 3;
+
+/// Upgrades the raw save data from version `i` to version `i + 1`
+const MIGRATIONS: [fn(&mut BTreeMap<Value, Value>) -> Result<(), String>; SAVE_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3
+];
 
 #[derive(Serialize, Deserialize)]
 pub struct SerializablePlayer {
     pub unlocked_effects: Vec<SerializableEffect>,
     pub money: u32,
     pub stats: Statistics,
-    pub random: Option<f32>,
-    pub menu_themes: Option<Vec<MenuTheme>>,
-    pub cur_menu_theme: Option<usize>
+    pub random: f32,
+    pub menu_themes: Vec<MenuTheme>,
+    pub cur_menu_theme: usize,
+    pub unlocked_songs: Vec<(String, Vec<f32>)>,
+    pub current_effect: Option<SerializableEffect>,
+    pub dreaming: bool
 }
 
 impl SerializablePlayer {
@@ -26,25 +46,41 @@ impl SerializablePlayer {
             unlocked_effects,
             money: player.money,
             stats: player.stats.clone(),
-            random: Some(player.random),
-            menu_themes: Some(player.menu_themes.clone()),
-            cur_menu_theme: Some(player.current_theme)
+            random: player.random,
+            menu_themes: player.menu_themes.clone(),
+            cur_menu_theme: player.current_theme,
+            unlocked_songs: player.unlocked_songs.clone(),
+            current_effect: player.current_effect.as_ref().map(|e| SerializableEffect::from_effect(e)),
+            dreaming: player.dreaming
         }
     }
 
     pub fn to_player<'a, T>(&self, creator: &'a TextureCreator<T>) -> Player<'a> {
         let mut player = Player::new(creator);
         for effect in self.unlocked_effects.iter() {
-            player.unlocked_effects.push(effect.to_effect());
+            match effect.to_effect() {
+                Ok(effect) => player.unlocked_effects.push(effect),
+                Err(e) => eprintln!("Warning: {}", e)
+            }
         }
 
-        let random = self.random.unwrap_or(rand::thread_rng().gen_range(0.0..1.0));
-        player.random = random;
+        player.random = self.random;
+        player.menu_themes = self.menu_themes.clone();
+        player.current_theme = self.cur_menu_theme;
+        player.unlocked_songs = self.unlocked_songs.clone();
 
-        let menu_themes = self.menu_themes.clone().unwrap_or(vec![MenuTheme::System]);
-        player.menu_themes = menu_themes;
-        let cur_theme= self.cur_menu_theme.unwrap_or(0);
-        player.current_theme = cur_theme;
+        if let Some(effect) = &self.current_effect {
+            // Applied directly instead of through apply_effect to skip the switch animation
+            match effect.to_effect() {
+                Ok(effect) => {
+                    effect.apply(&mut player);
+                    player.current_effect = Some(effect);
+                },
+                Err(e) => eprintln!("Warning: {}", e)
+            }
+        }
+
+        player.dreaming = self.dreaming;
 
         player
     }
@@ -62,46 +98,388 @@ impl SerializableEffect {
         }
     }
 
-    pub fn to_effect(&self) -> Effect {
-        Effect::parse(&self.effect).unwrap()
+    pub fn to_effect(&self) -> Result<Effect, String> {
+        Effect::parse(&self.effect).ok_or(format!("unknown effect \"{}\"", self.effect))
     }
 }
 
+/// A lua value that can be kept in a `persist` table
+#[derive(Serialize, Deserialize, Clone)]
+pub enum PersistValue {
+    Bool(bool),
+    Int(i64),
+    Number(f64),
+    String(String),
+    /// Key value pairs, keys are only ever strings or numbers
+    Table(Vec<(PersistValue, PersistValue)>)
+}
+
+/// The contents of every lua `persist` table
+#[derive(Serialize, Deserialize, Default)]
+pub struct PersistData {
+    /// The `global_persist` table shared by all scripts
+    pub global: Vec<(PersistValue, PersistValue)>,
+    /// Each script's own `persist` table, by script path
+    pub scripts: HashMap<String, Vec<(PersistValue, PersistValue)>>
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SaveData {
-    pub player: SerializablePlayer
+    pub version: u32,
+    pub player: SerializablePlayer,
+    pub global_flags: HashMap<String, i32>,
+    /// Name of the map the game was saved on
+    pub map: Option<String>,
+    /// Seconds since the unix epoch
+    pub timestamp: Option<u64>,
+    pub persist: PersistData
 }
 
 impl SaveData {
-    pub fn create(player: &Player) -> Self {
+    pub fn create(player: &Player, world: &World, persist: PersistData) -> Self {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).ok();
         Self {
-            player: SerializablePlayer::from_player(player)
+            version: SAVE_VERSION,
+            player: SerializablePlayer::from_player(player),
+            global_flags: world.global_flags.clone(),
+            map: Some(world.name.clone()),
+            timestamp,
+            persist
         }
     } 
 
+    /// Reads a save file, upgrading it to the current version if it was written by an older build<br>
+    /// Falls back to the newest backup that can be read if the save is missing or corrupt
+    pub fn load(path: &PathBuf) -> Result<Self, SaveLoadError> {
+        match Self::load_file(path) {
+            Err(error @ (SaveLoadError::Missing(_) | SaveLoadError::Corrupt(_))) => {
+                for i in 1..=SAVE_BACKUPS {
+                    let backup = backup_path(path, i);
+                    if let Ok(data) = Self::load_file(&backup) {
+                        eprintln!("Warning: {} ({}), loaded {} instead", path.display(), error, backup.display());
+                        return Ok(data);
+                    }
+                }
+
+                Err(error)
+            },
+            result => result
+        }
+    }
+
+    fn load_file(path: &PathBuf) -> Result<Self, SaveLoadError> {
+        let file = File::open(path).map_err(|e| SaveLoadError::Missing(e.to_string()))?;
+        let raw: Value = serde_cbor::from_reader(&file).map_err(|e| SaveLoadError::Corrupt(e.to_string()))?;
+        Self::from_raw(raw)
+    }
+
+    /// Upgrades untyped save data to the current version and reads it into the typed model
+    fn from_raw(raw: Value) -> Result<Self, SaveLoadError> {
+        let migrated = migrate(raw)?;
+
+        // Round trip through bytes since the typed model can only be read from a cbor stream
+        let bytes = serde_cbor::to_vec(&migrated).map_err(|e| SaveLoadError::Corrupt(e.to_string()))?;
+        serde_cbor::from_slice(&bytes).map_err(|e| SaveLoadError::Corrupt(e.to_string()))
+    }
+
+    /// Reads a save converted to json by `to_json`, upgrading it if it was exported by an older build
+    pub fn from_json(path: &PathBuf) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+        let raw: Value = serde_json::from_reader(&file).map_err(|e| format!("invalid save json: {}", e))?;
+        let data = Self::from_raw(raw).map_err(|e| e.to_string())?;
+
+        let problems = data.validate();
+        if !problems.is_empty() {
+            return Err(problems.join("\n"));
+        }
+
+        Ok(data)
+    }
+
+    /// Writes the save as pretty printed json
+    pub fn to_json(&self, path: &PathBuf) -> Result<(), Box<dyn Error>> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(&file, self)?;
+        Ok(())
+    }
+
+    /// Checks the parts of the save that serde can't, returning a description of every problem found
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (i, effect) in self.player.unlocked_effects.iter().enumerate() {
+            if let Err(e) = effect.to_effect() {
+                problems.push(format!("player.unlocked_effects[{}]: {}", i, e));
+            }
+        }
+
+        if let Some(effect) = &self.player.current_effect {
+            if let Err(e) = effect.to_effect() {
+                problems.push(format!("player.current_effect: {}", e));
+            }
+        }
+
+        if self.player.cur_menu_theme >= self.player.menu_themes.len() {
+            problems.push(format!("player.cur_menu_theme: {} is out of range for {} menu themes", self.player.cur_menu_theme, self.player.menu_themes.len()));
+        }
+
+        problems
+    }
+
     pub fn get_player<'a, T>(&self, creator: &'a TextureCreator<T>) -> Player<'a> {
         self.player.to_player(creator)
     }
 
+    pub fn get_global_flags(&self) -> HashMap<String, i32> {
+        self.global_flags.clone()
+    }
+
     pub fn save(&self, id: u32, name: &PathBuf, saves: &mut SaveInfo) -> Result<(), Box<dyn Error>> {
-        let mut file = File::create(name)?;
-        serde_cbor::to_writer(&mut file, &self)?;
+        rotate_backups(name)?;
+        write_atomic(name, self)?;
 
-        saves.update(id, SaveSlot::new(name, self.player.unlocked_effects.len()));
+        saves.update(id, SaveSlot::new(name, self));
 
         Ok(())
     }
 }
 
+/// Writes to a temporary file first and renames it over `path`, so a crash mid-write leaves the old file intact
+fn write_atomic<S: serde::Serialize>(path: &Path, data: &S) -> Result<(), Box<dyn Error>> {
+    let temp_path = temp_path(path);
+    let mut file = File::create(&temp_path)?;
+    serde_cbor::to_writer(&mut file, data)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp_name = path.as_os_str().to_owned();
+    temp_name.push(".tmp");
+    PathBuf::from(temp_name)
+}
+
+/// The thumbnail for `N.save` is stored next to it as `N.png`
+pub fn thumbnail_path(path: &Path) -> PathBuf {
+    path.with_extension("png")
+}
+
+/// Scales a screenshot down to thumbnail size and writes it as a png<br>
+/// `pixels` are RGBA32 with no row padding
+pub fn write_thumbnail(pixels: &mut [u8], width: u32, height: u32, path: &Path) -> Result<(), String> {
+    let mut screenshot = Surface::from_data(pixels, width, height, width * 4, PixelFormatEnum::RGBA32)?;
+    screenshot.set_blend_mode(BlendMode::None)?;
+    let mut thumbnail = Surface::new(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT, PixelFormatEnum::RGBA32)?;
+    screenshot.blit_scaled(None, &mut thumbnail, None)?;
+
+    // Written the same way as the save so a crash can't leave a truncated image
+    let temp_path = temp_path(path);
+    thumbnail.save(&temp_path)?;
+    fs::rename(&temp_path, path).map_err(|e| e.to_string())
+}
+
+fn backup_path(path: &Path, index: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".bak{}", index));
+    PathBuf::from(name)
+}
+
+/// Shifts the existing backups of a save down by one, dropping the oldest, and copies the current save into `.bak1`
+fn rotate_backups(path: &Path) -> Result<(), Box<dyn Error>> {
+    if !path.exists() || SAVE_BACKUPS == 0 {
+        return Ok(());
+    }
+
+    for i in (1..SAVE_BACKUPS).rev() {
+        let from = backup_path(path, i);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, i + 1))?;
+        }
+    }
+
+    // Copied instead of renamed so the slot always has a readable file
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+pub enum SaveLoadError {
+    Missing(String),
+    Corrupt(String),
+    /// The save was written by a newer version of the game
+    TooNew(u32)
+}
+
+impl SaveLoadError {
+    /// Short description shown on the load error screen
+    pub fn message(&self) -> String {
+        match self {
+            Self::Missing(_) => "The save file could not be found.".to_string(),
+            Self::Corrupt(_) => "The save file is damaged and could not be read.".to_string(),
+            Self::TooNew(_) => "This save was made by a newer version of the game.".to_string()
+        }
+    }
+}
+
+impl Display for SaveLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(e) => write!(f, "save file could not be opened: {}", e),
+            Self::Corrupt(e) => write!(f, "save data is corrupt: {}", e),
+            Self::TooNew(version) => write!(f, "save version {} is newer than the supported version {}", version, SAVE_VERSION)
+        }
+    }
+}
+
+fn key(name: &str) -> Value {
+    Value::Text(name.to_string())
+}
+
+/// Runs every migration between the save's version and `SAVE_VERSION` in order
+fn migrate(raw: Value) -> Result<Value, SaveLoadError> {
+    let mut map = match raw {
+        Value::Map(map) => map,
+        _ => return Err(SaveLoadError::Corrupt("top level is not a map".to_string()))
+    };
+
+    // Saves from before versioning have no version field
+    let version = match map.get(&key("version")) {
+        None => 0,
+        Some(Value::Integer(v)) => u32::try_from(*v).map_err(|_| SaveLoadError::Corrupt(format!("invalid version {}", v)))?,
+        Some(_) => return Err(SaveLoadError::Corrupt("version is not an integer".to_string()))
+    };
+
+    if version > SAVE_VERSION {
+        return Err(SaveLoadError::TooNew(version));
+    }
+
+    for step in version..SAVE_VERSION {
+        MIGRATIONS[step as usize](&mut map).map_err(|e| SaveLoadError::Corrupt(format!("migration from version {} failed: {}", step, e)))?;
+        map.insert(key("version"), Value::Integer((step + 1) as i128));
+    }
+
+    Ok(Value::Map(map))
+}
+
+/// Inserts `default` if `name` is missing or null
+fn insert_default(map: &mut BTreeMap<Value, Value>, name: &str, default: Value) {
+    if matches!(map.get(&key(name)), None | Some(Value::Null)) {
+        map.insert(key(name), default);
+    }
+}
+
+/// A number in `0.0..1.0` taken from a hash of `value`<br>
+/// Migrating the same save twice gives the same number, so loading an old save is reproducible
+fn content_random(value: &Value) -> f64 {
+    let mut hasher = DefaultHasher::new();
+    serde_cbor::to_vec(value).unwrap_or_default().hash(&mut hasher);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Version 0 saves only had the player, with the later player fields optional
+fn migrate_v0_to_v1(map: &mut BTreeMap<Value, Value>) -> Result<(), String> {
+    let player = match map.get_mut(&key("player")) {
+        Some(Value::Map(player)) => player,
+        _ => return Err("missing player data".to_string())
+    };
+
+    let random = content_random(&Value::Map(player.clone()));
+    insert_default(player, "random", Value::Float(random));
+    insert_default(player, "menu_themes", Value::Array(vec![key("System")]));
+    insert_default(player, "cur_menu_theme", Value::Integer(0));
+    insert_default(player, "unlocked_songs", Value::Array(Vec::new()));
+    insert_default(player, "current_effect", Value::Null);
+    insert_default(player, "dreaming", Value::Bool(false));
+
+    insert_default(map, "global_flags", Value::Map(BTreeMap::new()));
+
+    Ok(())
+}
+
+/// Version 2 added playtime, the map name and a timestamp
+fn migrate_v1_to_v2(map: &mut BTreeMap<Value, Value>) -> Result<(), String> {
+    let stats = match map.get_mut(&key("player")) {
+        Some(Value::Map(player)) => match player.get_mut(&key("stats")) {
+            Some(Value::Map(stats)) => stats,
+            _ => return Err("missing player statistics".to_string())
+        },
+        _ => return Err("missing player data".to_string())
+    };
+
+    insert_default(stats, "playtime", Value::Integer(0));
+
+    insert_default(map, "map", Value::Null);
+    insert_default(map, "timestamp", Value::Null);
+
+    Ok(())
+}
+
+/// Version 3 added lua persist tables
+fn migrate_v2_to_v3(map: &mut BTreeMap<Value, Value>) -> Result<(), String> {
+    let mut persist = BTreeMap::new();
+    persist.insert(key("global"), Value::Array(Vec::new()));
+    persist.insert(key("scripts"), Value::Map(BTreeMap::new()));
+    insert_default(map, "persist", Value::Map(persist));
+
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SaveSlot {
     pub file: String,
     pub effects: usize,
+    pub playtime: Option<u64>,
+    pub map: Option<String>,
+    pub timestamp: Option<u64>,
+    pub thumbnail: Option<String>
 }
 
 impl SaveSlot {
-    pub fn new(path: &PathBuf, effects: usize) -> Self {
-        Self { effects, file: path.to_str().expect("invalid save file name").to_string() }
+    pub fn new(path: &PathBuf, data: &SaveData) -> Self {
+        let mut slot = Self::unreadable(path);
+        slot.effects = data.player.unlocked_effects.len();
+        slot.playtime = Some(data.player.stats.playtime);
+        slot.map = data.map.clone();
+        slot.timestamp = data.timestamp;
+        slot
+    }
+
+    /// A slot for a save file whose contents couldn't be read
+    pub fn unreadable(path: &PathBuf) -> Self {
+        let thumbnail = thumbnail_path(path);
+        Self {
+            effects: 0,
+            file: path.to_str().expect("invalid save file name").to_string(),
+            playtime: None,
+            map: None,
+            timestamp: None,
+            thumbnail: if thumbnail.exists() { thumbnail.to_str().map(|t| t.to_string()) } else { None }
+        }
+    }
+
+    /// Playtime formatted as hours and minutes
+    pub fn playtime_string(&self) -> Option<String> {
+        let minutes = self.playtime? / 60000;
+        Some(format!("{}h {:02}m", minutes / 60, minutes % 60))
+    }
+
+    /// The date the slot was saved on as YYYY-MM-DD, in UTC
+    pub fn date_string(&self) -> Option<String> {
+        let days = self.timestamp? / 86400;
+
+        // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719468;
+        let era = z / 146097;
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Some(format!("{}-{:02}-{:02}", year, month, day))
     }
 }
 
@@ -118,28 +496,56 @@ impl SaveInfo {
     }
 
     pub fn read() -> Result<Self, Box<dyn Error>> {
-        let file = File::open("saves/.saves")?;
+        let file = File::open(paths::save_info())?;
         let read: Result<SaveInfo, serde_cbor::Error> = serde_cbor::from_reader(&file);
-        if let Ok(save) = read {
-            return Ok(save);
-        } else {
-            return Err(Box::new(read.err().unwrap()));
+        match read {
+            Ok(save) => return Ok(save),
+            Err(e) => {
+                eprintln!("Warning: save index is damaged ({}), rebuilding it from the save files", e);
+                return SaveInfo::rebuild();
+            }
         }
     }
 
+    /// Recreates the index from the `N.save` files in the save directory
+    pub fn rebuild() -> Result<Self, Box<dyn Error>> {
+        let mut files = BTreeMap::new();
+        for entry in fs::read_dir(paths::saves_dir())? {
+            let path = entry?.path();
+            if !path.extension().is_some_and(|ext| ext == "save") {
+                continue;
+            }
+
+            let id = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse::<u32>().ok());
+            if let Some(id) = id {
+                // Unreadable saves are still listed so that loading them shows the error screen
+                let slot = match SaveData::load(&path) {
+                    Ok(data) => SaveSlot::new(&path, &data),
+                    Err(e) => {
+                        eprintln!("Warning: {} could not be read while rebuilding the save index: {}", path.display(), e);
+                        SaveSlot::unreadable(&path)
+                    }
+                };
+                files.insert(id, slot);
+            }
+        }
+
+        let save_info = SaveInfo { files };
+        save_info.write()?;
+        Ok(save_info)
+    }
+
     pub fn create_new() -> Result<Self, Box<dyn Error>> {
         let save_data = SaveInfo {
             files: BTreeMap::new(),
             //files_ordered: BTreeMap::new()
         };
-        let mut write = File::create("saves/.saves")?;
-        serde_cbor::to_writer(&mut write, &save_data)?;
+        save_data.write()?;
         Ok(save_data)
     }
 
     pub fn read_or_create_new() -> Result<Self, Box<dyn Error>> {
-        let saves_path = Path::new("saves/.saves");
-        if saves_path.exists() {
+        if paths::save_info().exists() {
             return SaveInfo::read();
         } else {
             return SaveInfo::create_new();
@@ -147,9 +553,116 @@ impl SaveInfo {
     }
 
     pub fn write(&self) -> Result<(), Box<dyn Error>> {
-        let mut file = File::create("saves/.saves")?;
-        serde_cbor::to_writer(&mut file, self)?;
+        write_atomic(&paths::save_info(), self)
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_cbor::Value;
+
+    use super::{key, SaveData, SaveLoadError, SAVE_VERSION};
+
+    fn object(entries: Vec<(&str, Value)>) -> Value {
+        Value::Map(entries.into_iter().map(|(name, value)| (key(name), value)).collect::<BTreeMap<Value, Value>>())
+    }
+
+    fn migrated(raw: Value) -> SaveData {
+        SaveData::from_raw(raw).unwrap_or_else(|e| panic!("migration failed: {}", e))
     }
-}
\ No newline at end of file
+
+    /// A save from before versioning, with only the fields the first build wrote
+    fn v0_save() -> Value {
+        object(vec![
+            ("player", object(vec![
+                ("unlocked_effects", Value::Array(Vec::new())),
+                ("money", Value::Integer(5)),
+                ("stats", object(vec![
+                    ("steps", Value::Integer(120)),
+                    ("times_slept", Value::Integer(3))
+                ]))
+            ]))
+        ])
+    }
+
+    fn v2_save() -> Value {
+        object(vec![
+            ("version", Value::Integer(2)),
+            ("player", object(vec![
+                ("unlocked_effects", Value::Array(Vec::new())),
+                ("money", Value::Integer(7)),
+                ("stats", object(vec![
+                    ("steps", Value::Integer(400)),
+                    ("times_slept", Value::Integer(9)),
+                    ("playtime", Value::Integer(65000))
+                ])),
+                ("random", Value::Float(0.25)),
+                ("menu_themes", Value::Array(vec![key("System"), key("Vines")])),
+                ("cur_menu_theme", Value::Integer(1)),
+                ("unlocked_songs", Value::Array(vec![Value::Array(vec![key("song1"), Value::Array(vec![Value::Float(1.0)])])])),
+                ("current_effect", Value::Null),
+                ("dreaming", Value::Bool(true))
+            ])),
+            ("global_flags", object(vec![("met_bird", Value::Integer(1))])),
+            ("map", key("bedroom")),
+            ("timestamp", Value::Integer(1700000000))
+        ])
+    }
+
+    #[test]
+    fn migrates_v0_save() {
+        let data = migrated(v0_save());
+
+        assert_eq!(data.version, SAVE_VERSION);
+        assert_eq!(data.player.money, 5);
+        assert_eq!(data.player.stats.steps, 120);
+        assert_eq!(data.player.stats.times_slept, 3);
+        assert_eq!(data.player.stats.playtime, 0);
+        assert_eq!(data.player.menu_themes.len(), 1);
+        assert_eq!(data.player.cur_menu_theme, 0);
+        assert!(data.player.unlocked_songs.is_empty());
+        assert!(data.player.current_effect.is_none());
+        assert!(!data.player.dreaming);
+        assert!((0.0..1.0).contains(&data.player.random));
+        assert!(data.global_flags.is_empty());
+        assert_eq!(data.map, None);
+        assert_eq!(data.timestamp, None);
+        assert!(data.persist.global.is_empty());
+        assert!(data.persist.scripts.is_empty());
+    }
+
+    #[test]
+    fn v0_random_depends_only_on_save_contents() {
+        assert_eq!(migrated(v0_save()).player.random, migrated(v0_save()).player.random);
+    }
+
+    #[test]
+    fn migrates_v2_save() {
+        let data = migrated(v2_save());
+
+        assert_eq!(data.version, SAVE_VERSION);
+        assert_eq!(data.player.money, 7);
+        assert_eq!(data.player.stats.playtime, 65000);
+        assert_eq!(data.player.random, 0.25);
+        assert_eq!(data.player.cur_menu_theme, 1);
+        assert_eq!(data.player.unlocked_songs, vec![("song1".to_string(), vec![1.0])]);
+        assert!(data.player.dreaming);
+        assert_eq!(data.global_flags.get("met_bird"), Some(&1));
+        assert_eq!(data.map.as_deref(), Some("bedroom"));
+        assert_eq!(data.timestamp, Some(1700000000));
+        assert!(data.persist.global.is_empty());
+        assert!(data.persist.scripts.is_empty());
+    }
+
+    #[test]
+    fn rejects_newer_save() {
+        let mut save = v2_save();
+        if let Value::Map(map) = &mut save {
+            map.insert(key("version"), Value::Integer(SAVE_VERSION as i128 + 1));
+        }
+
+        assert!(matches!(SaveData::from_raw(save), Err(SaveLoadError::TooNew(version)) if version == SAVE_VERSION + 1));
+    }
+}
@@ -1,8 +1,8 @@
 use std::{fs, path::PathBuf};
 
-use sdl2::{keyboard::Keycode, pixels::Color, rect::Rect, render::{Canvas, RenderTarget, TextureCreator}};
+use sdl2::{pixels::Color, rect::Rect, render::{Canvas, RenderTarget, TextureCreator}};
 
-use crate::{audio::SoundEffectBank, game::{Input, RenderState}, texture::Texture};
+use crate::{audio::SoundEffectBank, controls::Action, game::{Input, RenderState}, texture::Texture};
 
 enum Continue {
     Use,
@@ -89,7 +89,7 @@ impl<'a> ScreenEvent<'a> {
     } 
 
     pub fn tick(&mut self, sfx: &mut SoundEffectBank, input: &Input, state: &mut RenderState) -> bool {
-        if input.get_just_pressed(Keycode::X) && self.can_exit {
+        if input.get_action_just_pressed(Action::Cancel) && self.can_exit {
             return false;
         }
 
@@ -193,7 +193,7 @@ impl<'a> ScreenEvent<'a> {
     pub fn cont(&self, input: &Input) -> bool {
         match self.steps[self.current_step].cont {
             Continue::Use => {
-                input.get_just_pressed(Keycode::Z)
+                input.get_action_just_pressed(Action::Confirm)
             },
             Continue::Wait(_) => {
                 self.timer == 0
@@ -1,9 +1,9 @@
 use std::{path::PathBuf, collections::HashMap};
 
 use rodio::Sink;
-use sdl2::{render::{RenderTarget, Canvas, TextureCreator}, rect::Rect, keyboard::Keycode, pixels::Color};
+use sdl2::{render::{RenderTarget, Canvas, TextureCreator}, rect::Rect, pixels::Color};
 
-use crate::{audio::{Song, SoundEffectBank}, effect::Effect, game::{Input, IntProperty, LevelPropertyType, QueuedLoad, RenderState, WarpPos}, player::{self, Player}, save::SaveInfo, texture::Texture, tiles::Tileset, transitions::{Transition, TransitionType}, world::World};
+use crate::{audio::{Song, SoundEffectBank}, controls::Action, effect::Effect, game::{Input, IntProperty, LevelPropertyType, QueuedLoad, RenderState, WarpPos}, player::{self, Player}, save::{self, SaveInfo}, texture::Texture, tiles::Tileset, transitions::{Transition, TransitionType}, world::World};
 
 const MENU_FRAME_TOP_RIGHT: u32 = 0;
 const MENU_FRAME_TOP: u32 = 1;
@@ -57,7 +57,10 @@ pub enum MenuType {
     /// True - save, False - load
     SaveLoad(bool),
 
-    MusicPlayer
+    MusicPlayer,
+
+    /// Shown when a save file can't be loaded, holds the message to display
+    LoadError(String)
 }
 
 pub struct MenuState {
@@ -92,7 +95,7 @@ impl MenuState {
     }
 
     pub fn update(&mut self, input: &Input, player: &mut Player, world: &mut World, save_info: &SaveInfo, sfx: &mut SoundEffectBank) {
-        if input.get_just_pressed(Keycode::X) && !input.get_pressed(Keycode::F3) {
+        if input.get_action_just_pressed(Action::Cancel) && !input.get_action_pressed(Action::Debug) {
             match self.current_menu {
                 MenuType::Effects | MenuType::Quit | MenuType::Special | MenuType::Me => {
                     if matches!(self.current_menu, MenuType::Effects) { self.button_id = 0; }
@@ -116,12 +119,18 @@ impl MenuState {
                         self.button_id = 1;
                         sfx.play_ex("menu_blip_negative", 1.0, 0.5);
                     }
+                },
+                MenuType::LoadError(_) => {
+                    self.current_menu = MenuType::MainMenu;
+                    self.close_on_x = false;
+                    self.button_id = 1;
+                    sfx.play_ex("menu_blip_negative", 1.0, 0.5);
                 }
                 _ => ()
             }
         }
 
-        if input.get_just_pressed(Keycode::Z) {
+        if input.get_action_just_pressed(Action::Confirm) {
             match self.current_menu {
                 MenuType::Home => {
                     match self.button_id {
@@ -278,6 +287,12 @@ impl MenuState {
                         }
                     }
                 },
+                MenuType::LoadError(_) => {
+                    self.current_menu = MenuType::MainMenu;
+                    self.close_on_x = false;
+                    self.button_id = 1;
+                    sfx.play_ex("menu_blip_affirmative", 1.0, 0.25);
+                },
                 MenuType::SaveConfirm => {
                     if self.button_id == 0 {
                         world.special_context.write_save_to_pending = true;
@@ -329,8 +344,8 @@ impl MenuState {
 
         match self.current_menu {
             MenuType::Home => {
-                if input.get_just_pressed(Keycode::Up) { self.button_id -= 1; }
-                if input.get_just_pressed(Keycode::Down) { self.button_id += 1; }
+                if input.get_action_just_pressed(Action::Up) { self.button_id -= 1; }
+                if input.get_action_just_pressed(Action::Down) { self.button_id += 1; }
                 if self.button_id >= BUTTONS_MAIN as i32 {
                     self.button_id = 0;
                 }
@@ -339,10 +354,10 @@ impl MenuState {
                 }
             },
             MenuType::Effects => {
-                if input.get_just_pressed(Keycode::Right) { self.button_id += 1; }
-                if input.get_just_pressed(Keycode::Down) { self.button_id += 2; }
-                if input.get_just_pressed(Keycode::Left) { self.button_id -= 1; }
-                if input.get_just_pressed(Keycode::Up) { self.button_id -= 2; }
+                if input.get_action_just_pressed(Action::Right) { self.button_id += 1; }
+                if input.get_action_just_pressed(Action::Down) { self.button_id += 2; }
+                if input.get_action_just_pressed(Action::Left) { self.button_id -= 1; }
+                if input.get_action_just_pressed(Action::Up) { self.button_id -= 2; }
                 if self.button_id >= player.unlocked_effects.len() as i32 {
                     self.button_id = 0;
                 }
@@ -351,8 +366,8 @@ impl MenuState {
                 }
             },
             MenuType::Quit | MenuType::SaveConfirm => {
-                if input.get_just_pressed(Keycode::Up) { self.button_id -= 1; }
-                if input.get_just_pressed(Keycode::Down) { self.button_id += 1; }
+                if input.get_action_just_pressed(Action::Up) { self.button_id -= 1; }
+                if input.get_action_just_pressed(Action::Down) { self.button_id += 1; }
                 if self.button_id > 1 {
                     self.button_id = 0;
                 }
@@ -361,8 +376,8 @@ impl MenuState {
                 }
             },
             MenuType::MainMenu => {
-                if input.get_just_pressed(Keycode::Up) { self.button_id -= 1; }
-                if input.get_just_pressed(Keycode::Down) { self.button_id += 1; }
+                if input.get_action_just_pressed(Action::Up) { self.button_id -= 1; }
+                if input.get_action_just_pressed(Action::Down) { self.button_id += 1; }
                 if self.button_id >= BUTTONS_TITLE as i32 {
                     self.button_id = 0;
                 }
@@ -371,10 +386,10 @@ impl MenuState {
                 }
             },
             MenuType::SaveLoad(b) => {
-                if input.get_just_pressed(Keycode::Up) { self.button_id -= 1; }
-                if input.get_just_pressed(Keycode::Down) { self.button_id += 1; }
-                if input.get_just_pressed(Keycode::Right) { self.page_index += 1; }
-                if input.get_just_pressed(Keycode::Left) { self.page_index -= 1; }
+                if input.get_action_just_pressed(Action::Up) { self.button_id -= 1; }
+                if input.get_action_just_pressed(Action::Down) { self.button_id += 1; }
+                if input.get_action_just_pressed(Action::Right) { self.page_index += 1; }
+                if input.get_action_just_pressed(Action::Left) { self.page_index -= 1; }
 
                 //let button_max = save_info.files.len() as i32;
                 //let button_max_load = ((save_info.files.len() - 1) % 3) as i32;
@@ -416,10 +431,10 @@ impl MenuState {
                 }
             },
             MenuType::MusicPlayer => {
-                if input.get_just_pressed(Keycode::Up) { self.button_id -= 1; }
-                if input.get_just_pressed(Keycode::Down) { self.button_id += 1; }
-                if input.get_just_pressed(Keycode::Right) { self.page_index += 1; }
-                if input.get_just_pressed(Keycode::Left) { self.page_index -= 1; }
+                if input.get_action_just_pressed(Action::Up) { self.button_id -= 1; }
+                if input.get_action_just_pressed(Action::Down) { self.button_id += 1; }
+                if input.get_action_just_pressed(Action::Right) { self.page_index += 1; }
+                if input.get_action_just_pressed(Action::Left) { self.page_index -= 1; }
 
                 let max_button = (player.unlocked_songs.len() as i32 - (16 * self.page_index)).min(16);
                 let max_pages = player.unlocked_songs.len() / 16;
@@ -450,8 +465,8 @@ impl MenuState {
             },
             MenuType::Special => {
                 let button_max = 1;
-                if input.get_just_pressed(Keycode::Up) { self.button_id -= 1; }
-                if input.get_just_pressed(Keycode::Down) { self.button_id += 1; }
+                if input.get_action_just_pressed(Action::Up) { self.button_id -= 1; }
+                if input.get_action_just_pressed(Action::Down) { self.button_id += 1; }
                 if self.button_id >= button_max {
                     self.button_id = 0;
                 }
@@ -476,8 +491,12 @@ pub struct Ui<'a> {
     pub effect_get: Option<String>,
     pub effect_get_timer: u32,
     pub player_preview_texture: Texture<'a>,
+    pub save_thumbnails: HashMap<u32, Texture<'a>>,
 }
 
+/// Longest map name that fits beside a save slot's thumbnail
+const SAVE_DETAILS_MAX_CHARS: usize = 21;
+
 const SONG_VARIANT_LABELS: [&str; 11] = ["A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K"];
 
 impl<'a> Ui<'a> {
@@ -490,7 +509,21 @@ impl<'a> Ui<'a> {
             menu_state: MenuState::new(),
             effect_get: None,
             effect_get_timer: 0,
-            player_preview_texture: Texture::from_file(&PathBuf::from("res/textures/misc/preview.png"), creator).expect("could not finish loading textures")
+            player_preview_texture: Texture::from_file(&PathBuf::from("res/textures/misc/preview.png"), creator).expect("could not finish loading textures"),
+            save_thumbnails: HashMap::new()
+        }
+    }
+
+    /// Reloads the slot thumbnails shown in the save and load menus
+    pub fn load_save_thumbnails<T>(&mut self, save_info: &SaveInfo, creator: &'a TextureCreator<T>) {
+        self.save_thumbnails.clear();
+        for (id, slot) in save_info.files.iter() {
+            if let Some(path) = &slot.thumbnail {
+                match Texture::from_file(&PathBuf::from(path), creator) {
+                    Ok(texture) => { self.save_thumbnails.insert(*id, texture); },
+                    Err(e) => eprintln!("Warning: could not load save thumbnail {}: {}", path, e)
+                }
+            }
         }
     }
 
@@ -527,7 +560,8 @@ impl<'a> Ui<'a> {
             world.special_context.open_music_menu = false;
         }
         
-        if input.get_just_pressed(Keycode::X) && !input.get_pressed(Keycode::F3) && self.effect_get.is_none() {
+        let toggle_menu = input.get_action_just_pressed(Action::Menu) || (self.open && input.get_action_just_pressed(Action::Cancel));
+        if toggle_menu && !input.get_action_pressed(Action::Debug) && self.effect_get.is_none() {
             if self.open && self.menu_state.close_on_x {
                 //sink.play();
                 match self.menu_state.current_menu {
@@ -720,13 +754,36 @@ impl<'a> Ui<'a> {
                             self.theme.draw_button(canvas, 14 + 4, y as i32 + 9, 48, &slot_message, selected_button == id as i32, self.menu_state.selection_flash);
                             self.theme.font.draw_string(canvas, "Katrin", (14 + 8, y as i32 + 9 + 16 + 1));
                             self.theme.font.draw_string(canvas, &effects_message, (14 + 8, y as i32 + 9 + 32));
-                            canvas.copy(
-                                &self.player_preview_texture.texture,
-                                None,
-                                Rect::new(
-                                    100, y as i32 + 8, 48, 48
-                                )
-                            ).unwrap();
+                            if let Some(thumbnail) = self.save_thumbnails.get(&id) {
+                                canvas.copy(
+                                    &thumbnail.texture,
+                                    None,
+                                    Rect::new(
+                                        100, y as i32 + 8, save::THUMBNAIL_WIDTH, save::THUMBNAIL_HEIGHT
+                                    )
+                                ).unwrap();
+                            } else {
+                                canvas.copy(
+                                    &self.player_preview_texture.texture,
+                                    None,
+                                    Rect::new(
+                                        100, y as i32 + 8, 48, 48
+                                    )
+                                ).unwrap();
+                            }
+
+                            let details_x = 100 + save::THUMBNAIL_WIDTH as i32 + 8;
+                            if let Some(map) = &entry.map {
+                                // The font has no underscores
+                                let map_name: String = map.replace('_', " ").chars().take(SAVE_DETAILS_MAX_CHARS).collect();
+                                self.theme.font.draw_string(canvas, &map_name, (details_x, y as i32 + 9));
+                            }
+                            if let Some(playtime) = entry.playtime_string() {
+                                self.theme.font.draw_string(canvas, &playtime, (details_x, y as i32 + 9 + 16 + 1));
+                            }
+                            if let Some(date) = entry.date_string() {
+                                self.theme.font.draw_string(canvas, &date, (details_x, y as i32 + 9 + 32));
+                            }
                             y += 64;
                         }
                     }
@@ -783,6 +840,13 @@ impl<'a> Ui<'a> {
                         self.theme.draw_button_strikethrough(canvas, buttons_x, buttons_y, buttons_width as i32, "Wake Up", self.menu_state.button_id == 0, self.menu_state.selection_flash);
                     }
                 }
+                MenuType::LoadError(ref message) => {
+                    self.theme.draw_frame(canvas, 0, 0, state.screen_extents.0 / 16, 2);
+                    self.theme.font.draw_string(canvas, "Could not load save", (11, 11));
+                    self.theme.draw_frame(canvas, 0, 32, state.screen_extents.0 / 16, 6);
+                    self.theme.font.draw_string_wrapped(canvas, message, (11, 32 + 11), state.screen_extents.0 - 22);
+                    self.theme.draw_button(canvas, 6, 32 + 64 + 6, 48, "Back", true, self.menu_state.selection_flash);
+                }
                 _ => {
                     let width = self.theme.font.string_width("...");
                     self.theme.font.draw_string(canvas, "...", ((state.screen_extents.0 as i32 / 2) - (width as i32 / 2), (state.screen_extents.1 as i32 / 2) - (self.theme.font.char_height as i32 / 2)));
@@ -0,0 +1,183 @@
+use std::{collections::{HashSet, VecDeque}, path::Path};
+
+use json::JsonValue;
+use tiled::{LayerType, Loader, PropertyValue};
+
+use crate::loader;
+
+/// One warp from a map to another, found in an object's actions or the map's edges
+pub struct Warp {
+    pub from: String,
+    pub to: String,
+    /// The target position, `None` when it is computed at runtime
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    /// Where the warp was found, like `object 12` or `edge left`
+    pub source: String
+}
+
+/// How all maps connect through warps<br>
+/// Map names are relative to `res/maps/`, the same way warp actions name them
+pub struct WarpGraph {
+    pub maps: Vec<String>,
+    pub warps: Vec<Warp>,
+    pub start: String
+}
+
+fn map_name(path: &Path) -> String {
+    path.strip_prefix(loader::MAPS_DIR).unwrap_or(path).to_string_lossy().replace('\\', "/")
+}
+
+/// Collects every warp nested in an action
+fn find_warps(action: &JsonValue, from: &str, source: &str, warps: &mut Vec<Warp>) {
+    if action.is_object() {
+        if action["type"].as_str() == Some("warp") {
+            if let Some(to) = action["map"].as_str() {
+                warps.push(Warp {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    x: action["pos"]["x"].as_i32(),
+                    y: action["pos"]["y"].as_i32(),
+                    source: source.to_string()
+                });
+            }
+        }
+
+        for (_, field) in action.entries() {
+            find_warps(field, from, source, warps);
+        }
+    } else if action.is_array() {
+        for member in action.members() {
+            find_warps(member, from, source, warps);
+        }
+    }
+}
+
+impl WarpGraph {
+    /// Loads every map under `res/maps/` and collects their warps
+    pub fn build() -> Result<Self, String> {
+        let paths = loader::map_files(Path::new(loader::MAPS_DIR)).map_err(|e| format!("could not read {}: {}", loader::MAPS_DIR, e))?;
+        let mut graph = Self {
+            maps: paths.iter().map(|path| map_name(path)).collect(),
+            warps: Vec::new(),
+            start: map_name(Path::new(crate::START_MAP))
+        };
+
+        for (path, name) in paths.iter().zip(graph.maps.iter()) {
+            let map = match Loader::new().load_tmx_map(path) {
+                Ok(map) => map,
+                Err(e) => {
+                    eprintln!("Warning: could not load {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+
+            if let Some(PropertyValue::StringValue(edges)) = map.properties.get("edges") {
+                if let Ok(parsed) = json::parse(edges) {
+                    for (side, action) in parsed.entries() {
+                        find_warps(action, name, &format!("edge {}", side), &mut graph.warps);
+                    }
+                }
+            }
+
+            for layer in map.layers() {
+                if let LayerType::Objects(object_layer) = layer.layer_type() {
+                    for object in object_layer.objects() {
+                        // Merging the file in substitutes properties like `door_warp_map` into its actions
+                        let properties = match loader::merged_properties(&object.properties) {
+                            Ok(properties) => properties,
+                            Err(e) => {
+                                eprintln!("Warning: {} object {}: {}", name, object.id(), e);
+                                continue;
+                            }
+                        };
+
+                        if let Some(PropertyValue::StringValue(actions)) = properties.get("actions") {
+                            if let Ok(parsed) = json::parse(actions) {
+                                for entry in parsed.members() {
+                                    find_warps(&entry["action"], name, &format!("object {}", object.id()), &mut graph.warps);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(graph)
+    }
+
+    /// Warps whose target map doesn't exist
+    pub fn missing_targets(&self) -> Vec<&Warp> {
+        self.warps.iter().filter(|warp| !self.maps.contains(&warp.to)).collect()
+    }
+
+    /// Maps that can't be reached by warping from the start map<br>
+    /// Maps only entered from lua scripts will show up here too
+    pub fn unreachable(&self) -> Vec<&String> {
+        let mut reached = HashSet::new();
+        let mut queue = VecDeque::new();
+        reached.insert(&self.start);
+        queue.push_back(&self.start);
+
+        while let Some(map) = queue.pop_front() {
+            for warp in self.warps.iter().filter(|warp| &warp.from == map) {
+                if reached.insert(&warp.to) {
+                    queue.push_back(&warp.to);
+                }
+            }
+        }
+
+        self.maps.iter().filter(|map| !reached.contains(map)).collect()
+    }
+
+    pub fn to_dot(&self) -> String {
+        let unreachable = self.unreachable();
+        let mut dot = String::from("digraph warps {\n");
+        for map in self.maps.iter() {
+            let style = if map == &self.start { " [shape=doublecircle]" } else if unreachable.contains(&map) { " [style=dashed]" } else { "" };
+            dot.push_str(&format!("    \"{}\"{};\n", map, style));
+        }
+        for warp in self.missing_targets() {
+            dot.push_str(&format!("    \"{}\" [color=red];\n", warp.to));
+        }
+        for warp in self.warps.iter() {
+            let x = warp.x.map(|x| x.to_string()).unwrap_or("?".to_string());
+            let y = warp.y.map(|y| y.to_string()).unwrap_or("?".to_string());
+            dot.push_str(&format!("    \"{}\" -> \"{}\" [label=\"{}, {}\"];\n", warp.from, warp.to, x, y));
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let unreachable = self.unreachable();
+        let mut maps = JsonValue::new_array();
+        for map in self.maps.iter() {
+            let mut node = JsonValue::new_object();
+            node["name"] = map.as_str().into();
+            node["reachable"] = (!unreachable.contains(&map)).into();
+            maps.push(node).unwrap();
+        }
+
+        let mut warps = JsonValue::new_array();
+        for warp in self.warps.iter() {
+            let mut edge = JsonValue::new_object();
+            edge["from"] = warp.from.as_str().into();
+            edge["to"] = warp.to.as_str().into();
+            edge["x"] = warp.x.into();
+            edge["y"] = warp.y.into();
+            edge["source"] = warp.source.as_str().into();
+            edge["missing"] = (!self.maps.contains(&warp.to)).into();
+            warps.push(edge).unwrap();
+        }
+
+        let mut graph = JsonValue::new_object();
+        graph["start"] = self.start.as_str().into();
+        graph["maps"] = maps;
+        graph["warps"] = warps;
+
+        graph
+    }
+}
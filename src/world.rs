@@ -1,12 +1,12 @@
 use std::{cell::RefCell, cmp::Ordering, collections::HashMap, path::PathBuf, rc::Rc};
 
 use json::JsonValue;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rodio::Sink;
 use sdl2::{render::{Canvas, RenderTarget, Texture, TextureCreator, TextureAccess}, rect::{Rect, Point}, pixels::{Color, PixelFormatEnum}};
 use serde_derive::{Deserialize, Serialize};
 
-use crate::{actions::Action, audio::{Song, SoundEffectBank}, effect::Effect, entity::{Entity, Trigger, VariableValue}, game::{self, BoolProperty, EntityPropertyType, Input, IntProperty, QueuedLoad, RenderState}, lua::ScriptingContext, player::{MenuTheme, Player}, screen_event::ScreenEvent, texture, tiles::{SpecialTile, Tile, Tilemap, Tileset}, transitions::{Transition, TransitionTextures}};
+use crate::{actions::Action, audio::{Song, SoundEffectBank}, effect::Effect, entity::{Entity, Trigger, VariableValue}, game::{self, BoolProperty, EntityPropertyType, Input, IntProperty, QueuedLoad, RenderState}, lua::{self, ScriptCall, ScriptingContext}, player::{MenuTheme, Player}, screen_event::ScreenEvent, texture, tiles::{SpecialTile, Tile, Tilemap, Tileset}, transitions::{Transition, TransitionTextures}};
 
 const RAINDROPS_LIFETIME: u32 = 10;
 const RAINDROPS_PER_CYCLE: usize = 3;
@@ -43,19 +43,31 @@ pub struct QueuedEntityAction {
 #[derive(Clone)]
 pub struct RandomState {
     pub level_random: f32,
-    pub session_random: f32
+    pub session_random: f32,
+    /// Everything that changes how the game plays draws from this
+    pub rng: StdRng,
+    /// Particles, rain and snow draw from their own stream, so drawing or not drawing doesn't change the game
+    pub effects: StdRng
 }
 
 impl RandomState {
-    pub fn new() -> Self {
+    /// All randomness in a run comes from `seed`, the same seed and input replay the same way
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let effects = StdRng::seed_from_u64(rng.gen());
+        let level_random = rng.gen_range(0.0..1.0);
+        let session_random = rng.gen_range(0.0..1.0);
+
         Self {
-            level_random: rand::thread_rng().gen_range(0.0..1.0),
-            session_random: rand::thread_rng().gen_range(0.0..1.0)
+            level_random,
+            session_random,
+            rng,
+            effects
         }
     }
 
     pub fn level(mut self) -> Self {
-        self.level_random = rand::thread_rng().gen_range(0.0..1.0);
+        self.level_random = self.rng.gen_range(0.0..1.0);
         self
     }
 }
@@ -104,6 +116,8 @@ pub struct World<'a> {
     pub raindrops: RaindropsInfo,
     pub snow: SnowInfo,
     pub source_file: PathBuf,
+    /// Lua script for the whole map, from the map's `script` property
+    pub script: Option<PathBuf>,
     pub particle_textures: ParticleTextures<'a>,
 
     pub screen_events: HashMap<String, ScreenEvent<'a>>,
@@ -173,13 +187,15 @@ impl<'a> World<'a> {
             raindrops: RaindropsInfo::new(),
             snow: SnowInfo::new(),
             source_file: PathBuf::new(),
+            script: None,
             particle_textures: ParticleTextures::new(),
             running_screen_event: None,
             screen_events: HashMap::new(),
             pre_event_song: None,
             entity_draw_order: Vec::new(),
             player_draw_slot: None,
-            random: RandomState::new()
+            // The game replaces this with its own seeded state
+            random: RandomState::new(0)
         }
     }
 
@@ -226,6 +242,7 @@ impl<'a> World<'a> {
             raindrops: RaindropsInfo::new(),
             snow: SnowInfo::new(),
             source_file: PathBuf::new(),
+            script: None,
             particle_textures: ParticleTextures::new(),
             running_screen_event: None,
             screen_events: HashMap::new(),
@@ -285,6 +302,8 @@ impl<'a> World<'a> {
         } else {
             sink.set_volume(0.0);
         }
+        scripts.clear_entity_scripts();
+        scripts.set_world_script(self.script.as_ref());
         for entity in self.entities.as_mut().unwrap().iter_mut() {
             for action in &mut entity.actions {
                 if action.trigger.contains_trigger(&Trigger::OnLoad) {
@@ -293,7 +312,7 @@ impl<'a> World<'a> {
             }
 
             if let Some(script) = &entity.script {
-                scripts.add_entity_script(entity.id, &script);
+                scripts.add_entity_script(entity.id, script);
             }
         }
 
@@ -328,7 +347,7 @@ impl<'a> World<'a> {
         self.entities.as_mut().unwrap().push(entity);
     }
 
-    pub fn update(&mut self, player: &mut Player, sfx: &mut SoundEffectBank, sink: &Sink, input: &Input, state: &mut RenderState) {
+    pub fn update(&mut self, player: &mut Player, sfx: &mut SoundEffectBank, sink: &Sink, input: &Input, state: &mut RenderState, scripts: &mut ScriptingContext) {
         self.timer += 1;
         if let Some(transition) = &mut self.transition {
             if transition.holding {
@@ -417,6 +436,7 @@ impl<'a> World<'a> {
             }
 
             let mut act_entities = Vec::new();
+            let mut script_interactions = Vec::new();
 
             let mut entity_list = self.entities.take().unwrap();
             let mut placeholder = Some(Entity::new());
@@ -426,6 +446,7 @@ impl<'a> World<'a> {
                 placeholder = Some(std::mem::replace(entity_list.get_mut(i).unwrap(), entity));
             }
             self.entities = Some(entity_list);
+            scripts.run_ai_scripts(self, player);
 
             for inter in self.interactions.iter() {
                 match inter {
@@ -452,6 +473,9 @@ impl<'a> World<'a> {
                         entity.interaction = Some(
                             (inter.clone(), player.facing.flipped())
                         );
+                        if entity.script.is_some() {
+                            script_interactions.push((i, lua::interaction_callback(inter), player.facing.flipped()));
+                        }
                         for (j, action) in entity.actions.iter().enumerate() {
                             if action.trigger.fulfilled_interaction(inter, Some(player.facing.flipped())) {
                                 act_entities.push((i, j));
@@ -486,6 +510,24 @@ impl<'a> World<'a> {
                 self.entities.as_mut().unwrap().insert(*i, entity);
             }
 
+            if player.effect_just_changed {
+                for (i, entity) in self.entities.as_ref().unwrap().iter().enumerate() {
+                    if entity.script.is_some() {
+                        script_interactions.push((i, lua::EFFECT_SWITCH_CALLBACK, player.facing));
+                    }
+                }
+            }
+
+            for (i, callback, side) in script_interactions.into_iter() {
+                let mut entity = std::mem::replace(self.entities.as_mut().unwrap().get_mut(i).unwrap(), placeholder.take().unwrap());
+                if callback == lua::EFFECT_SWITCH_CALLBACK {
+                    scripts.call_entity_callback(callback, &mut entity, self, player, ());
+                } else {
+                    scripts.call_entity_callback(callback, &mut entity, self, player, side.name());
+                }
+                placeholder = Some(std::mem::replace(self.entities.as_mut().unwrap().get_mut(i).unwrap(), entity));
+            }
+
             let mut action_opt = None;
 
             for i in 0..self.queued_entity_actions.len() {
@@ -540,6 +582,7 @@ impl<'a> World<'a> {
                 }
             }
             self.special_context.entity_context.entity_call = false;
+            scripts.run_script_calls(self, player);
 
             if let Some(id) = self.special_context.entity_removal_queue.pop() {
                 self.entities.as_mut().unwrap().remove(id);
@@ -589,7 +632,7 @@ impl<'a> World<'a> {
             }
 
             if self.special_context.new_session {
-                self.random.session_random = rand::thread_rng().gen_range(0.0..1.0);
+                self.random.session_random = self.random.rng.gen_range(0.0..1.0);
                 self.special_context.new_session = false;
             }
 
@@ -760,12 +803,10 @@ impl<'a> World<'a> {
     }
 
     pub fn post_draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, state: &RenderState) {
-        let mut rng = rand::thread_rng();
-
         if self.raindrops.enabled {
             for _ in 0..RAINDROPS_PER_CYCLE {
-                let x = rng.gen_range(0..state.screen_extents.0) as i32 - state.offset.0;
-                let y = rng.gen_range(0..state.screen_extents.1) as i32 - state.offset.1;
+                let x = self.random.effects.gen_range(0..state.screen_extents.0) as i32 - state.offset.0;
+                let y = self.random.effects.gen_range(0..state.screen_extents.1) as i32 - state.offset.1;
 
                 //let special = self.get_special_in_layer(height, x, y)
                 let tile = ((x / 16).rem_euclid(self.width as i32) as u32, (y / 16).rem_euclid(self.height as i32) as u32);
@@ -796,8 +837,8 @@ impl<'a> World<'a> {
 
         if self.snow.enabled {
             for _ in 0..SNOW_PER_CYCLE {
-                let x = rng.gen_range(0..state.screen_extents.0) as i32 - state.offset.0;
-                let y = rng.gen_range(-80..state.screen_extents.1 as i32) - state.offset.1;
+                let x = self.random.effects.gen_range(0..state.screen_extents.0) as i32 - state.offset.0;
+                let y = self.random.effects.gen_range(-80..state.screen_extents.1 as i32) - state.offset.1;
 
                 self.snow.snow.push(Snow {
                     lifetime: SNOW_LIFETIME,
@@ -1249,7 +1290,7 @@ impl<'a> World<'a> {
     }
 }
 
-const PARTICLE_IMAGES_PATH: &str = "res/textures/particle/";
+pub const PARTICLE_IMAGES_PATH: &str = "res/textures/particle/";
 
 pub struct ParticleTextures<'a> {
     pub textures: HashMap<String, texture::Texture<'a>>
@@ -1448,7 +1489,13 @@ pub struct SpecialContext {
     pub open_music_menu: bool,
 
     pub unlock_menu_theme: Option<MenuTheme>,
-    pub cycle_menu_theme: bool
+    pub cycle_menu_theme: bool,
+
+    /// lua functions called by actions, run at the end of the entity update
+    pub script_calls: Vec<ScriptCall>,
+
+    /// Entity id and tick function of every `script` ai that ran this update
+    pub script_ai_ticks: Vec<(u32, String)>
 }
 
 struct Raindrop {
@@ -1512,7 +1559,9 @@ impl SpecialContext {
             new_session: false,
             open_music_menu: false,
             cycle_menu_theme: false,
-            unlock_menu_theme: None
+            unlock_menu_theme: None,
+            script_calls: Vec::new(),
+            script_ai_ticks: Vec::new()
         }
     }
 }